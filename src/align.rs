@@ -1,23 +1,233 @@
+use log::warn;
 use ndarray::{Array, Array2};
 use ndarray_stats::QuantileExt;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
 
 use crate::InversionError;
+use crate::compressed_steps::CompressedSteps;
+use crate::graph::PangenomeGraph;
+use crate::scoring::{GapPenalties, PathScores, ScoringScheme};
 
-use super::{amax, argmax, lowmem};
+use super::{amax, argmax, chain, hirschberg, lowmem};
 
+/// The algorithm used to align a subproblem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentAlgorithm {
+    /// full dynamic programming matrices, for short subproblems
+    Highmem,
+
+    /// banded dynamic programming keeping only two rows of scores, for long subproblems
+    Lowmem,
+
+    /// exact dynamic programming in linear memory, for long subproblems if `exact_lowmem` is set
+    Hirschberg,
+
+    /// chaining of shared segments, for the whole paths at once (see `chain`)
+    Chain,
+}
+
+/// How to find the inversions between two paths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlignmentStrategy {
+    /// align each subproblem with dynamic programming, which is exact but quadratic in the length
+    /// of the subproblem
+    DynamicProgramming,
+
+    /// chain the segments that the paths traverse in opposite orientations (see `chain`), which
+    /// takes O(n log n) time, with a penalty of `gap_cost` for each bp skipped between segments of
+    /// a chain
+    Chaining { gap_cost: f64 },
+}
+
+/// Default penalty for each bp skipped between the segments of a chain, so that skipping the same
+/// amount of both paths costs as much as leaving it unaligned in one of them
+pub const DEFAULT_CHAIN_GAP_COST: f64 = 0.5;
+
+impl fmt::Display for AlignmentStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlignmentStrategy::DynamicProgramming => write!(f, "dp"),
+            AlignmentStrategy::Chaining { gap_cost } => write!(f, "chain:{}", gap_cost),
+        }
+    }
+}
+
+/// Parse the name of an alignment strategy: `dp` for `DynamicProgramming`, or `chain` or
+/// `chain:<gap_cost>` for `Chaining`.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::align::{self, AlignmentStrategy};
+///
+/// assert_eq!(
+///     align::parse_strategy("chain:0.1").unwrap(),
+///     AlignmentStrategy::Chaining { gap_cost: 0.1 }
+/// );
+/// assert_eq!(align::parse_strategy("chain").unwrap().to_string(), "chain:0.5");
+/// assert!(align::parse_strategy("chain:-1").is_err());
+/// ```
+pub fn parse_strategy(name: &str) -> Result<AlignmentStrategy, InversionError> {
+    let invalid = |reason: &str| InversionError::InvalidStrategy(format!("{} ({})", name, reason));
+    match name.split_once(':') {
+        None if name == "dp" => Ok(AlignmentStrategy::DynamicProgramming),
+        None if name == "chain" => Ok(AlignmentStrategy::Chaining {
+            gap_cost: DEFAULT_CHAIN_GAP_COST,
+        }),
+        Some(("chain", gap_cost)) => match gap_cost.parse::<f64>() {
+            Ok(gap_cost) if gap_cost.is_finite() && gap_cost >= 0.0 => {
+                Ok(AlignmentStrategy::Chaining { gap_cost })
+            }
+            _ => Err(invalid("the gap cost must be a number of at least 0")),
+        },
+        _ => Err(invalid("expected dp, chain, or chain:<gap_cost>")),
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Alignment {
     /// segments aligned in path1
-    pub alignment_path1: Vec<i32>,
+    pub alignment_path1: Vec<i64>,
 
     /// segments aligned in path2
-    pub alignment_path2: Vec<i32>,
+    pub alignment_path2: Vec<i64>,
 
     /// start index of alignment in path1
-    pub path1_start_index: i32,
+    pub path1_start_index: usize,
 
     /// end index of alignment in path1
-    pub path1_end_index: i32,
+    pub path1_end_index: usize,
+
+    /// start index of alignment in path2
+    pub path2_start_index: usize,
+
+    /// end index of alignment in path2
+    pub path2_end_index: usize,
+
+    /// alignment score
+    pub score: i64,
+
+    /// number of segments of path1 and path2 in the subproblem that the alignment was found in
+    pub subproblem_size: (usize, usize),
+
+    /// algorithm used to align the subproblem
+    pub algorithm: AlignmentAlgorithm,
+}
+
+/// Parameters for aligning paths
+#[derive(Clone, Copy)]
+pub struct AlignmentOptions<'a> {
+    /// subproblems longer than this are aligned with the lowmem algorithm
+    pub max_highmem_path_length: usize,
+
+    /// maximum drop for the lowmem heuristic
+    pub max_lowmem_drop: usize,
+
+    /// align subproblems longer than `max_highmem_path_length` exactly in linear memory (see
+    /// `hirschberg`) rather than with the banded lowmem heuristic, which is slower but finds the
+    /// same alignments as the highmem algorithm
+    pub exact_lowmem: bool,
+
+    /// subproblems longer than this are not aligned at all, unless `exact_lowmem` is set
+    pub max_path_length: usize,
+
+    /// if given, report every non-overlapping local alignment in each subproblem scoring at least
+    /// this much (see `align_subproblem_local`) rather than only the best one
+    pub min_alignment_score: Option<i64>,
+
+    /// how to score the segments in the alignments
+    pub scoring_scheme: &'a dyn ScoringScheme,
+
+    /// affine gap penalties for runs of unaligned segments
+    pub gap_penalties: GapPenalties,
+
+    /// whether to align subproblems with dynamic programming or to chain shared segments
+    pub strategy: AlignmentStrategy,
+
+    /// when chaining, align up to this many steps of the paths on either side of each end of a
+    /// chain with dynamic programming to find exactly where the inversion starts and ends
+    pub refine_window: Option<usize>,
+}
+
+/// Score standing in for minus infinity where a run of unaligned segments can't end, low enough
+/// that it never wins but high enough that adding scores to it doesn't overflow
+pub(crate) const NO_GAP_RUN: i64 = i64::MIN / 4;
+
+/// Flag in a traceback value meaning that the run of unaligned segments of path1 ending at the
+/// cell continues the one ending at the cell above, rather than starting there
+pub(crate) const EXTEND_UP: i8 = 4;
+
+/// Flag in a traceback value meaning that the run of unaligned segments of path2 ending at the
+/// cell continues the one ending at the cell to the left, rather than starting there
+pub(crate) const EXTEND_LEFT: i8 = 8;
+
+/// Find the score of the best run of unaligned segments of one path ending at a cell (Gotoh's
+/// algorithm), which either starts after the best alignment ending at the cell before it or
+/// continues the best run ending there.
+///
+/// Returns the score and whether the run continues the previous one.
+pub(crate) fn gap_run_score(
+    previous_score: i64,
+    previous_run_score: i64,
+    gap_open: i64,
+    gap_extension: i64,
+) -> (i64, bool) {
+    let start_score = previous_score - gap_open;
+    if previous_run_score > start_score {
+        (previous_run_score + gap_extension, true)
+    } else {
+        (start_score + gap_extension, false)
+    }
+}
+
+/// Which matrix of Gotoh's algorithm a traceback is following
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TracebackState {
+    /// the best alignment ending at the cell
+    Best,
+
+    /// the best alignment ending at the cell with a run of unaligned segments of path1
+    GapUp,
+
+    /// the best alignment ending at the cell with a run of unaligned segments of path2
+    GapLeft,
+}
+
+/// Take a step of a traceback from cell `(i, j)` with traceback value `value`, returning the next
+/// cell, or `None` if the alignment starts here.
+pub(crate) fn traceback_step(
+    value: i8,
+    state: &mut TracebackState,
+    (i, j): (usize, usize),
+) -> Option<(usize, usize)> {
+    // the best alignment ending at a cell can end with a run of unaligned segments ending at the
+    // same cell, so switch to following the run before moving
+    if *state == TracebackState::Best {
+        match value & 3 {
+            0 => return None,
+            1 => return Some((i - 1, j - 1)),
+            2 => *state = TracebackState::GapUp,
+            3 => *state = TracebackState::GapLeft,
+            _ => unreachable!(),
+        }
+    }
+    match state {
+        TracebackState::GapUp => {
+            if value & EXTEND_UP == 0 {
+                *state = TracebackState::Best;
+            }
+            Some((i - 1, j))
+        }
+        TracebackState::GapLeft => {
+            if value & EXTEND_LEFT == 0 {
+                *state = TracebackState::Best;
+            }
+            Some((i, j - 1))
+        }
+        TracebackState::Best => unreachable!(),
+    }
 }
 
 /// Create alignment matrices with edges filled.
@@ -25,75 +235,100 @@ pub struct Alignment {
 /// Arguments:
 ///
 /// * `path1` and `path2`: vectors of oriented segments in paths to align
-/// * `segment_lengths`: map of segment ID to segment length
+/// * `path1_scores` and `path2_scores`: scores of the segments in the paths
+/// * `gap_open`: penalty for starting a run of unaligned segments
 ///
 /// Returns:
 ///
 /// * `score_matrix`: a DP alignment matrix for score with first row and column filled
 /// * `traceback_matrix`: a DP alignment matrix for traceback with first row and column filled.
 ///   Values: 0 => alignment starts here, 1 => alignment comes from diagonal, 2 => alignment comes
-///   from above, 3 => alignment comes from left
+///   from above, 3 => alignment comes from left, plus `EXTEND_UP` or `EXTEND_LEFT` if a run of
+///   unaligned segments ending here continues the one before it
 fn create_matrices(
-    path1: &[i32],
-    path2: &[i32],
-    segment_lengths: &HashMap<i32, i32>,
-) -> Result<(Array2<i32>, Array2<i8>), InversionError> {
-    let mut score_matrix: Array2<i32> = Array::zeros((path1.len(), path2.len()));
+    path1: &[i64],
+    path2: &[i64],
+    path1_scores: &PathScores,
+    path2_scores: &PathScores,
+    gap_open: i64,
+) -> (Array2<i64>, Array2<i8>) {
+    let mut score_matrix: Array2<i64> = Array::zeros((path1.len(), path2.len()));
     let mut traceback_matrix: Array2<i8> = Array::zeros((path1.len(), path2.len()));
 
     // fill in the corner
     score_matrix[[0, 0]] = if path1[0] == path2[0] {
-        *segment_lengths
-            .get(&path1[0].abs())
-            .ok_or(InversionError::SegmentNotFound(path1[0].abs()))?
+        path1_scores.matches[0]
     } else {
-        -1 * (*segment_lengths
-            .get(&path1[0].abs())
-            .ok_or(InversionError::SegmentNotFound(path1[0].abs()))?
-            + *segment_lengths
-                .get(&path2[0].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[0].abs()))?)
+        path1_scores.gaps[0] + path2_scores.gaps[0]
     };
 
     // fill in the first column
+    let mut gap_run = NO_GAP_RUN;
     for i in 1..path1.len() {
-        let this_cell_score = if path1[i] == path2[0] {
-            *segment_lengths
-                .get(&path1[i].abs())
-                .ok_or(InversionError::SegmentNotFound(path1[i].abs()))?
+        let mut gap_flags = 0;
+        let possible_scores = if path1[i] == path2[0] {
+            gap_run = NO_GAP_RUN;
+            let match_i = path1_scores.matches[i];
+            [
+                match_i,
+                NO_GAP_RUN,
+                score_matrix[[i - 1, 0]] + match_i,
+                NO_GAP_RUN,
+            ]
         } else {
-            -1 * *segment_lengths
-                .get(&path1[i].abs())
-                .ok_or(InversionError::SegmentNotFound(path1[i].abs()))?
+            let (score, extends) = gap_run_score(
+                score_matrix[[i - 1, 0]],
+                gap_run,
+                gap_open,
+                path1_scores.gap_extensions[i],
+            );
+            gap_run = score;
+            if extends {
+                gap_flags = EXTEND_UP;
+            }
+            [path1_scores.gaps[i], NO_GAP_RUN, gap_run, NO_GAP_RUN]
         };
 
-        let possible_scores = [0, -1, score_matrix[[i - 1, 0]], -1];
-        score_matrix[[i, 0]] = amax(&possible_scores) + this_cell_score;
-        traceback_matrix[[i, 0]] = argmax(&possible_scores)
-            .try_into()
-            .expect("Traceback values must be >=0");
+        score_matrix[[i, 0]] = amax(&possible_scores);
+        traceback_matrix[[i, 0]] = i8::try_from(argmax(&possible_scores))
+            .expect("Traceback values must be >=0")
+            | gap_flags;
     }
 
     // fill in the first row
+    let mut gap_run = NO_GAP_RUN;
     for j in 1..path2.len() {
-        let this_cell_score = if path2[j] == path1[0] {
-            *segment_lengths
-                .get(&path2[j].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[j].abs()))?
+        let mut gap_flags = 0;
+        let possible_scores = if path2[j] == path1[0] {
+            gap_run = NO_GAP_RUN;
+            let match_j = path2_scores.matches[j];
+            [
+                match_j,
+                NO_GAP_RUN,
+                NO_GAP_RUN,
+                score_matrix[[0, j - 1]] + match_j,
+            ]
         } else {
-            -1 * *segment_lengths
-                .get(&path2[j].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[j].abs()))?
+            let (score, extends) = gap_run_score(
+                score_matrix[[0, j - 1]],
+                gap_run,
+                gap_open,
+                path2_scores.gap_extensions[j],
+            );
+            gap_run = score;
+            if extends {
+                gap_flags = EXTEND_LEFT;
+            }
+            [path2_scores.gaps[j], NO_GAP_RUN, NO_GAP_RUN, gap_run]
         };
 
-        let possible_scores = [0, -1, -1, score_matrix[[0, j - 1]]];
-        score_matrix[[0, j]] = amax(&possible_scores) + this_cell_score;
-        traceback_matrix[[0, j]] = argmax(&possible_scores)
-            .try_into()
-            .expect("Traceback values must be >=0");
+        score_matrix[[0, j]] = amax(&possible_scores);
+        traceback_matrix[[0, j]] = i8::try_from(argmax(&possible_scores))
+            .expect("Traceback values must be >=0")
+            | gap_flags;
     }
 
-    return Ok((score_matrix, traceback_matrix));
+    (score_matrix, traceback_matrix)
 }
 
 /// Perform an alignment subproblem.
@@ -104,65 +339,146 @@ fn create_matrices(
 /// # Arguments
 ///
 /// * `path1` and `path2`: paths to align
-/// * `segment_lengths`: map of segment ID to segment length in bp
+/// * `graph`: graph containing the segments, for their lengths
+/// * `scoring_scheme`: how to score the segments
+/// * `gap_penalties`: affine gap penalties for runs of unaligned segments
 ///
 /// # Returns
 ///
 /// * `alignment_path1` and `alignment_path2`: alignment for both paths
-/// * `path1_start_index` and `path1_end_index`: indices of start and end segments of alignment in
-///    path1
-fn align_paths_subproblem(
-    path1: &[i32],
-    path2: &[i32],
-    segment_lengths: &HashMap<i32, i32>,
+/// * `path1_start_index`, `path1_end_index`, `path2_start_index`, and `path2_end_index`: indices
+///   of start and end segments of alignment in each path
+/// * `score`: the score of the alignment
+pub(crate) fn align_paths_subproblem(
+    path1: &[i64],
+    path2: &[i64],
+    graph: &PangenomeGraph,
+    scoring_scheme: &dyn ScoringScheme,
+    gap_penalties: GapPenalties,
 ) -> Result<Alignment, InversionError> {
+    let (score_matrix, traceback_matrix) =
+        fill_matrices(path1, path2, graph, scoring_scheme, gap_penalties)?;
+    let end = score_matrix.argmax().unwrap();
+    Ok(traceback(
+        path1,
+        path2,
+        &score_matrix,
+        &traceback_matrix,
+        end,
+    ))
+}
+
+/// Perform an alignment subproblem, but find the best alignment ending with the last segments of
+/// both paths rather than the best alignment anywhere. This is used to extend a chain from one of
+/// its ends (see `chain`).
+pub(crate) fn align_paths_subproblem_to_end(
+    path1: &[i64],
+    path2: &[i64],
+    graph: &PangenomeGraph,
+    scoring_scheme: &dyn ScoringScheme,
+    gap_penalties: GapPenalties,
+) -> Result<Alignment, InversionError> {
+    let (score_matrix, traceback_matrix) =
+        fill_matrices(path1, path2, graph, scoring_scheme, gap_penalties)?;
+    let end = (path1.len() - 1, path2.len() - 1);
+    Ok(traceback(
+        path1,
+        path2,
+        &score_matrix,
+        &traceback_matrix,
+        end,
+    ))
+}
+
+/// Fill in the score and traceback matrices of an alignment subproblem.
+fn fill_matrices(
+    path1: &[i64],
+    path2: &[i64],
+    graph: &PangenomeGraph,
+    scoring_scheme: &dyn ScoringScheme,
+    gap_penalties: GapPenalties,
+) -> Result<(Array2<i64>, Array2<i8>), InversionError> {
+    let path1_scores = PathScores::new(path1, graph, scoring_scheme, gap_penalties)?;
+    let path2_scores = PathScores::new(path2, graph, scoring_scheme, gap_penalties)?;
+    let gap_open = gap_penalties.open;
     let (mut score_matrix, mut traceback_matrix) =
-        create_matrices(&path1, &path2, &segment_lengths)?;
+        create_matrices(path1, path2, &path1_scores, &path2_scores, gap_open);
 
+    // scores of the best runs of unaligned segments of path1 ending at each cell of the previous
+    // row, and of path2 ending at the previous cell of this row; these are the other two matrices
+    // of Gotoh's algorithm, but only their traceback is kept for the whole matrix
+    let mut gap_runs_up = vec![NO_GAP_RUN; path2.len()];
     for i in 1..path1.len() {
-        let len_i = *segment_lengths
-            .get(&path1[i].abs())
-            .ok_or(InversionError::SegmentNotFound(path1[i].abs()))?;
+        let match_i = path1_scores.matches[i];
+        let gap_i = path1_scores.gaps[i];
+        let gap_extension_i = path1_scores.gap_extensions[i];
+        let mut gap_run_left = NO_GAP_RUN;
         for j in 1..path2.len() {
-            let len_j = *segment_lengths
-                .get(&path2[j].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[j]))?;
+            let gap_j = path2_scores.gaps[j];
+            let mut gap_flags = 0;
             let possible_scores = if path1[i] == path2[j] {
+                gap_runs_up[j] = NO_GAP_RUN;
+                gap_run_left = NO_GAP_RUN;
                 [
-                    len_i,
-                    score_matrix[[i - 1, j - 1]] + len_i, // come from diagonal
-                    score_matrix[[i - 1, j]] + len_i,     // come from above
-                    score_matrix[[i, j - 1]] + len_i,     // come from left
+                    match_i,
+                    score_matrix[[i - 1, j - 1]] + match_i, // come from diagonal
+                    score_matrix[[i - 1, j]] + match_i,     // come from above
+                    score_matrix[[i, j - 1]] + match_i,     // come from left
                 ]
             } else {
+                let (gap_run_up, extends_up) = gap_run_score(
+                    score_matrix[[i - 1, j]],
+                    gap_runs_up[j],
+                    gap_open,
+                    gap_extension_i,
+                );
+                let (score, extends_left) = gap_run_score(
+                    score_matrix[[i, j - 1]],
+                    gap_run_left,
+                    gap_open,
+                    path2_scores.gap_extensions[j],
+                );
+                gap_runs_up[j] = gap_run_up;
+                gap_run_left = score;
+                if extends_up {
+                    gap_flags |= EXTEND_UP;
+                }
+                if extends_left {
+                    gap_flags |= EXTEND_LEFT;
+                }
                 [
-                    -len_i - len_j,
-                    score_matrix[[i - 1, j - 1]] - len_i - len_j,
-                    score_matrix[[i - 1, j]] - len_i,
-                    score_matrix[[i, j - 1]] - len_j,
+                    gap_i + gap_j,
+                    score_matrix[[i - 1, j - 1]] + gap_i + gap_j,
+                    gap_run_up,   // end a run of unaligned segments of path1
+                    gap_run_left, // end a run of unaligned segments of path2
                 ]
             };
 
             score_matrix[[i, j]] = amax(&possible_scores);
-            traceback_matrix[[i, j]] = argmax(&possible_scores)
-                .try_into()
-                .expect("Traceback values must be >=0");
+            traceback_matrix[[i, j]] = i8::try_from(argmax(&possible_scores))
+                .expect("Traceback values must be >=0")
+                | gap_flags;
         }
     }
-    return Ok(traceback(&path1, &path2, &score_matrix, &traceback_matrix));
+    Ok((score_matrix, traceback_matrix))
 }
 
+/// Follow the traceback matrix back from cell `end` to find the alignment ending there.
 fn traceback(
-    path1: &[i32],
-    path2: &[i32],
-    score_matrix: &Array2<i32>,
+    path1: &[i64],
+    path2: &[i64],
+    score_matrix: &Array2<i64>,
     traceback_matrix: &Array2<i8>,
+    end: (usize, usize),
 ) -> Alignment {
-    let (mut i, mut j) = score_matrix.argmax().unwrap();
+    let (mut i, mut j) = end;
+    let score = score_matrix[[i, j]];
     let path1_end_index = i;
+    let path2_end_index = j;
+    let mut state = TracebackState::Best;
     let mut alignment_end_reached = false;
-    let mut alignment_path1: Vec<i32> = Vec::new();
-    let mut alignment_path2: Vec<i32> = Vec::new();
+    let mut alignment_path1: Vec<i64> = Vec::new();
+    let mut alignment_path2: Vec<i64> = Vec::new();
     while !alignment_end_reached {
         let segment_path1 = path1[i];
         let segment_path2 = path2[j];
@@ -173,41 +489,193 @@ fn traceback(
             alignment_path2.push(segment_path2);
         }
 
-        match traceback_matrix[[i, j]] {
-            0 => alignment_end_reached = true,
-            1 => {
-                i -= 1;
-                j -= 1;
-            }
-            2 => i -= 1,
-            3 => j -= 1,
-            x => panic!("Bad value {} in traceback matrix!", x),
+        match traceback_step(traceback_matrix[[i, j]], &mut state, (i, j)) {
+            Some(cell) => (i, j) = cell,
+            None => alignment_end_reached = true,
         }
     }
     let path1_start_index = i;
+    let path2_start_index = j;
     alignment_path1.reverse();
     alignment_path2.reverse();
-    return Alignment {
+    Alignment {
         alignment_path1,
         alignment_path2,
-        path1_start_index: path1_start_index.try_into().expect("i32 overflow"),
-        path1_end_index: path1_end_index.try_into().expect("i32 overflow"),
+        path1_start_index,
+        path1_end_index,
+        path2_start_index,
+        path2_end_index,
+        score,
+        subproblem_size: (path1.len(), path2.len()),
+        algorithm: AlignmentAlgorithm::Highmem,
+    }
+}
+
+/// A rectangular block of the dynamic programming matrix of a subproblem, and the best alignment
+/// in it, with indices relative to the start of the block
+struct MatrixBlock {
+    rows: Range<usize>,
+    columns: Range<usize>,
+    alignment: Alignment,
+}
+
+/// Split a range of rows or columns around the ones used by an alignment, returning it unchanged
+/// if they don't overlap.
+fn split_range(range: &Range<usize>, used: &Range<usize>) -> Vec<Range<usize>> {
+    if used.start >= range.end || used.end <= range.start {
+        return vec![range.clone()];
+    }
+    [range.start..used.start, used.end..range.end]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Find every non-overlapping local alignment in a subproblem scoring at least `min_score`, in
+/// the style of Waterman and Eggert, rather than only the best one.
+///
+/// The best alignment is found first, and then the rows and columns of the dynamic programming
+/// matrix that it covers are taken out, so that no segment is in more than one alignment. This
+/// splits the rest of the matrix into blocks that no alignment can cross, and the best alignment in
+/// each block is found with `align_subproblem`. The best alignment of all the blocks is taken
+/// next, and so on until no block has an alignment scoring at least `min_score`. Only the blocks
+/// split by an alignment have to be aligned again, so every block is only aligned once.
+///
+/// # Arguments
+///
+/// * `path1` and `path2`: paths to align
+/// * `min_score`: minimum score of the alignments to report
+/// * `align_subproblem`: function that finds the best alignment of two paths, e.g.,
+///   `align_paths_subproblem`
+///
+/// # Returns
+///
+/// * the alignments in the order they were found, i.e., from best to worst, with indices into
+///   `path1` and `path2`
+fn align_subproblem_local<F>(
+    path1: &[i64],
+    path2: &[i64],
+    min_score: i64,
+    mut align_subproblem: F,
+) -> Result<Vec<Alignment>, InversionError>
+where
+    F: FnMut(&[i64], &[i64]) -> Result<Alignment, InversionError>,
+{
+    let mut align_block = |rows: Range<usize>, columns: Range<usize>| {
+        align_subproblem(&path1[rows.clone()], &path2[columns.clone()]).map(|alignment| {
+            MatrixBlock {
+                rows,
+                columns,
+                alignment,
+            }
+        })
     };
+    let mut blocks = vec![align_block(0..path1.len(), 0..path2.len())?];
+    let mut alignments = Vec::new();
+    // take the first of the best alignments each time so that ties are always broken the same way
+    while let Some(block) = blocks
+        .iter()
+        .rev()
+        .filter(|block| block.alignment.score >= min_score)
+        .max_by_key(|block| block.alignment.score)
+    {
+        let alignment = Alignment {
+            alignment_path1: block.alignment.alignment_path1.clone(),
+            alignment_path2: block.alignment.alignment_path2.clone(),
+            path1_start_index: block.rows.start + block.alignment.path1_start_index,
+            path1_end_index: block.rows.start + block.alignment.path1_end_index,
+            path2_start_index: block.columns.start + block.alignment.path2_start_index,
+            path2_end_index: block.columns.start + block.alignment.path2_end_index,
+            subproblem_size: (path1.len(), path2.len()),
+            ..block.alignment
+        };
+        let used_rows = alignment.path1_start_index..alignment.path1_end_index + 1;
+        let used_columns = alignment.path2_start_index..alignment.path2_end_index + 1;
+
+        let mut new_blocks = Vec::new();
+        for block in blocks {
+            let row_parts = split_range(&block.rows, &used_rows);
+            let column_parts = split_range(&block.columns, &used_columns);
+            if row_parts == [block.rows.clone()] && column_parts == [block.columns.clone()] {
+                new_blocks.push(block);
+                continue;
+            }
+            for rows in &row_parts {
+                for columns in &column_parts {
+                    new_blocks.push(align_block(rows.clone(), columns.clone())?);
+                }
+            }
+        }
+        blocks = new_blocks;
+        alignments.push(alignment);
+    }
+    Ok(alignments)
 }
 
+/// Turn an alignment of a subproblem of path1 and the reverse complement of path2, starting at
+/// `subproblem1_start` and `subproblem2_start`, into one with indices into path1 and path2 and
+/// `alignment_path2` in the orientation of path2.
+pub(crate) fn unreverse_alignment(
+    alignment: Alignment,
+    subproblem1_start: usize,
+    subproblem2_start: usize,
+    path2_len: usize,
+) -> Alignment {
+    // path2 was reversed for the subproblem, so its indices need to be flipped back
+    let path2_rev_index = |j| path2_len - 1 - (subproblem2_start + j);
+    Alignment {
+        alignment_path2: alignment.alignment_path2.iter().rev().map(|x| -x).collect(),
+        path1_start_index: subproblem1_start + alignment.path1_start_index,
+        path1_end_index: subproblem1_start + alignment.path1_end_index,
+        path2_start_index: path2_rev_index(alignment.path2_end_index),
+        path2_end_index: path2_rev_index(alignment.path2_start_index),
+        ..alignment
+    }
+}
+
+/// Find the inversions of path2 relative to path1 by aligning path1 to the reverse complement of
+/// path2.
+///
+/// Segments are scored by `alignment_options.scoring_scheme` using their lengths in `graph`, not
+/// counting any bases they overlap with the preceding step. With the `Chaining` strategy, the
+/// segments shared by the paths are chained instead (see `chain`).
+///
+/// # Arguments
+///
+/// * `path1` and `path2`: paths to align
+/// * `graph`: graph containing the paths' segments
+/// * `alignment_options`: parameters for the alignments
+///
+/// # Returns
+///
+/// * an alignment for each inversion found, with indices into `path1` and `path2` and
+///   `alignment_path2` in the orientation of `path2`
 pub fn align_paths(
-    path1: &[i32],
-    path2: &[i32],
-    segment_lengths: &HashMap<i32, i32>,
-    max_highmem_length: usize,
-    max_lowmem_drop: usize,
-    max_path_length: usize,
+    path1: &CompressedSteps,
+    path2: &CompressedSteps,
+    graph: &PangenomeGraph,
+    alignment_options: AlignmentOptions,
 ) -> Result<Vec<Alignment>, InversionError> {
+    let AlignmentOptions {
+        max_highmem_path_length,
+        max_lowmem_drop,
+        exact_lowmem,
+        max_path_length,
+        min_alignment_score,
+        scoring_scheme,
+        gap_penalties,
+        strategy,
+        refine_window: _,
+    } = alignment_options;
+
     // reverse-complemented version of path2
-    let path2_rev: Vec<i32> = path2.iter().map(|x| -1 * x).rev().collect();
-    let path1_set = HashSet::<_>::from_iter(path1.iter().cloned());
-    let path2_set = HashSet::<_>::from_iter(path2.iter().cloned());
-    let path2_rev_set = HashSet::<_>::from_iter(path2_rev.iter().cloned());
+    let path2_rev = path2.reverse_complement();
+    if let AlignmentStrategy::Chaining { gap_cost } = strategy {
+        return chain::align_paths_chained(path1, &path2_rev, graph, gap_cost, alignment_options);
+    }
+    let path1_set = HashSet::<_>::from_iter(path1);
+    let path2_set = HashSet::<_>::from_iter(path2);
+    let path2_rev_set = HashSet::<_>::from_iter(&path2_rev);
     // all segments traversed in the same direction by path1 and path2
     let conflicting_segments =
         HashSet::<_>::from_iter(path1_set.intersection(&path2_set).map(|x| x.abs()));
@@ -216,96 +684,186 @@ pub fn align_paths(
     let intersection =
         HashSet::<_>::from_iter(path1_set.intersection(&path2_rev_set).map(|x| x.abs()));
     let common_segments = HashSet::<_>::from_iter(intersection.difference(&conflicting_segments));
-    let mut used_segments: HashSet<i32> = HashSet::new();
+    let mut used_segments: HashSet<i64> = HashSet::new();
+
+    // index of the first step of each common segment in the reverse complement of path2, found in
+    // one pass so that it doesn't have to be decoded from the start for every subproblem
+    let mut path2_rev_first_steps: HashMap<i64, usize> = HashMap::new();
+    for (i, segment) in path2_rev.iter().enumerate() {
+        if common_segments.contains(&segment.abs()) {
+            path2_rev_first_steps.entry(segment).or_insert(i);
+        }
+    }
 
     let mut alignments = Vec::new();
 
-    for subproblem1_start in 0..path1.len() {
-        if common_segments.contains(&path1[subproblem1_start].abs())
-            && !used_segments.contains(&path1[subproblem1_start].abs())
+    // the paths are only decoded a subproblem at a time, so that long paths don't have to be
+    // decoded all at once
+    for (subproblem1_start, first_segment) in path1.iter().enumerate() {
+        if common_segments.contains(&first_segment.abs())
+            && !used_segments.contains(&first_segment.abs())
         {
-            let mut subproblem1_end = subproblem1_start;
-            while subproblem1_end < path1.len()
-                && !conflicting_segments.contains(&path1[subproblem1_end].abs())
-                && !used_segments.contains(&path1[subproblem1_end].abs())
-            {
-                subproblem1_end += 1;
-            }
-            let path1_subproblem = &path1[subproblem1_start..subproblem1_end];
-
-            let subproblem2_start = path2_rev
-                .iter()
-                .position(|&x| x == path1[subproblem1_start])
-                .unwrap();
-            let mut subproblem2_end = subproblem2_start;
-            while subproblem2_end < path2_rev.len()
-                && !conflicting_segments.contains(&path2_rev[subproblem2_end].abs())
-                && !used_segments.contains(&path2_rev[subproblem2_end].abs())
-            {
-                subproblem2_end += 1;
-            }
-            let path2_subproblem = &path2_rev[subproblem2_start..subproblem2_end];
+            let in_subproblem = |segment: &i64| {
+                !conflicting_segments.contains(&segment.abs())
+                    && !used_segments.contains(&segment.abs())
+            };
+            let subproblem1_end = subproblem1_start
+                + path1
+                    .iter_from(subproblem1_start)
+                    .take_while(in_subproblem)
+                    .count();
+            let path1_subproblem = path1.decode(subproblem1_start..subproblem1_end);
+
+            let subproblem2_start =
+                *path2_rev_first_steps.get(&first_segment).ok_or_else(|| {
+                    InversionError::InvalidAlignment(format!(
+                        "segment {} is not in the reverse complement of path2",
+                        first_segment
+                    ))
+                })?;
+            let subproblem2_end = subproblem2_start
+                + path2_rev
+                    .iter_from(subproblem2_start)
+                    .take_while(in_subproblem)
+                    .count();
+            let path2_subproblem = path2_rev.decode(subproblem2_start..subproblem2_end);
 
-            // choose correct alignment algorithm depending on length
-            let alignment_option = if path1_subproblem.len() < max_highmem_length
-                && path2_subproblem.len() < max_highmem_length
+            // choose correct alignment algorithm depending on length. The exact lowmem algorithm
+            // only needs linear memory, so it aligns subproblems of any length.
+            let algorithm = if path1_subproblem.len() < max_highmem_path_length
+                && path2_subproblem.len() < max_highmem_path_length
             {
-                Some(align_paths_subproblem(
-                    path1_subproblem,
-                    path2_subproblem,
-                    segment_lengths,
-                )?)
+                Some(AlignmentAlgorithm::Highmem)
+            } else if exact_lowmem {
+                Some(AlignmentAlgorithm::Hirschberg)
             } else if path1_subproblem.len() < max_path_length
                 && path2_subproblem.len() < max_path_length
             {
-                Some(lowmem::align_paths_subproblem_lowmem(
-                    path1_subproblem,
-                    path2_subproblem,
-                    segment_lengths,
-                    max_lowmem_drop,
-                )?)
+                Some(AlignmentAlgorithm::Lowmem)
             } else {
+                warn!(
+                    "Skipping alignment subproblem of length {}x{} starting at path1 step {}: \
+                     longer than the maximum path length {}",
+                    path1_subproblem.len(),
+                    path2_subproblem.len(),
+                    subproblem1_start,
+                    max_path_length
+                );
                 None
             };
+            let align_subproblem = |path1: &[i64], path2: &[i64]| match algorithm {
+                Some(AlignmentAlgorithm::Highmem) => {
+                    align_paths_subproblem(path1, path2, graph, scoring_scheme, gap_penalties)
+                }
+                Some(AlignmentAlgorithm::Hirschberg) => {
+                    hirschberg::align_paths_subproblem_hirschberg(
+                        path1,
+                        path2,
+                        graph,
+                        scoring_scheme,
+                        gap_penalties,
+                    )
+                }
+                _ => lowmem::align_paths_subproblem_lowmem(
+                    path1,
+                    path2,
+                    graph,
+                    scoring_scheme,
+                    gap_penalties,
+                    max_lowmem_drop,
+                ),
+            };
+            let subproblem_alignments = match (algorithm, min_alignment_score) {
+                (None, _) => {
+                    // mark the whole subproblem as used so that its tail isn't tried again as a
+                    // shorter subproblem starting at each of its later segments
+                    for segment in path1_subproblem.iter().chain(&path2_subproblem) {
+                        used_segments.insert(segment.abs());
+                    }
+                    Vec::new()
+                }
+                (Some(_), None) => vec![align_subproblem(&path1_subproblem, &path2_subproblem)?],
+                (Some(_), Some(min_score)) => align_subproblem_local(
+                    &path1_subproblem,
+                    &path2_subproblem,
+                    min_score,
+                    align_subproblem,
+                )?,
+            };
 
-            if let Some(alignment) = alignment_option {
+            for alignment in subproblem_alignments {
                 for segment in &alignment.alignment_path1 {
                     used_segments.insert(segment.abs());
                 }
                 for segment in &alignment.alignment_path2 {
                     used_segments.insert(segment.abs());
                 }
-                alignments.push(Alignment {
-                    alignment_path1: alignment.alignment_path1,
-                    alignment_path2: alignment
-                        .alignment_path2
-                        .iter()
-                        .rev()
-                        .map(|x| -1 * x)
-                        .collect(),
-                    path1_start_index: (subproblem1_start as i32) + alignment.path1_start_index,
-                    path1_end_index: (subproblem1_start as i32) + alignment.path1_end_index,
-                });
+                alignments.push(unreverse_alignment(
+                    alignment,
+                    subproblem1_start,
+                    subproblem2_start,
+                    path2.len(),
+                ));
             }
         }
     }
-
-    return Ok(alignments);
+    Ok(alignments)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scoring::{SegmentLength, Sublinear};
     use ndarray::array;
 
+    fn test_options(
+        max_highmem_path_length: usize,
+        min_alignment_score: Option<i64>,
+    ) -> AlignmentOptions<'static> {
+        AlignmentOptions {
+            max_highmem_path_length,
+            max_lowmem_drop: 1000,
+            exact_lowmem: false,
+            max_path_length: 100000,
+            min_alignment_score,
+            scoring_scheme: &SegmentLength,
+            gap_penalties: GapPenalties::default(),
+            strategy: AlignmentStrategy::DynamicProgramming,
+            refine_window: None,
+        }
+    }
+
+    /// Make a graph with segments 1 to `num_segments`, where segments in both paths are 100 bp
+    /// long and all others are 10 bp long.
+    fn make_test_graph(path1: &[i64], path2: &[i64], num_segments: i64) -> PangenomeGraph {
+        let in_path = |path: &[i64], i: i64| path.contains(&i) || path.contains(&(-i));
+        PangenomeGraph::from_segment_lengths(
+            (1..=num_segments)
+                .map(|i| {
+                    if in_path(path1, i) && in_path(path2, i) {
+                        100
+                    } else {
+                        10
+                    }
+                })
+                .collect(),
+            Vec::new(),
+        )
+        .unwrap()
+    }
+
     #[test]
     fn test_create_matrices() {
         let path1 = vec![2, 3, 4, -5];
         let path2 = vec![2, 7, -5];
-        let segment_lengths: HashMap<i32, i32> =
-            HashMap::from([(2, 100), (3, 10), (4, 10), (5, 100), (7, 10)]);
-        let (score_matrix, traceback_matrix) =
-            create_matrices(&path1, &path2, &segment_lengths).unwrap();
+        let graph = make_test_graph(&path1, &path2, 7);
+        let (score_matrix, traceback_matrix) = create_matrices(
+            &path1,
+            &path2,
+            &PathScores::new(&path1, &graph, &SegmentLength, GapPenalties::default()).unwrap(),
+            &PathScores::new(&path2, &graph, &SegmentLength, GapPenalties::default()).unwrap(),
+            0,
+        );
         assert_eq!(
             score_matrix,
             array![[100, 90, -10], [90, 0, 0], [80, 0, 0], [-20, 0, 0]]
@@ -319,58 +877,57 @@ mod tests {
 
     #[test]
     fn test_align_subproblem() {
-        let path1: Vec<i32> = vec![2, 3, 4, -5, 6];
-        let path2: Vec<i32> = vec![6, 2, 7, -5];
-        let mut segment_lengths: HashMap<i32, i32> = HashMap::new();
-        for i in 0..8 {
-            segment_lengths.insert(
-                i,
-                if (path1.contains(&i) || path1.contains(&(-i)))
-                    && (path2.contains(&i) || path2.contains(&(-i)))
-                {
-                    100
-                } else {
-                    10
-                },
-            );
-        }
-        //let (path1_alignment, path2_alignment, path1_start_index, path1_end_index) =
-        let alignment = align_paths_subproblem(&path1, &path2, &segment_lengths).unwrap();
+        let path1: Vec<i64> = vec![2, 3, 4, -5, 6];
+        let path2: Vec<i64> = vec![6, 2, 7, -5];
+        let graph = make_test_graph(&path1, &path2, 7);
+        let alignment = align_paths_subproblem(
+            &path1,
+            &path2,
+            &graph,
+            &SegmentLength,
+            GapPenalties::default(),
+        )
+        .unwrap();
         assert_eq!(alignment.alignment_path1, vec![2, 3, 4, -5]);
         assert_eq!(alignment.alignment_path2, vec![2, 7, -5]);
         assert_eq!(alignment.path1_start_index, 0);
         assert_eq!(alignment.path1_end_index, 3);
+        assert_eq!(alignment.path2_start_index, 1);
+        assert_eq!(alignment.path2_end_index, 3);
+        assert_eq!(alignment.score, 170);
     }
 
     #[test]
     fn test_align_paths() {
         let path1 = vec![1, 2, 3, 4, 5, 6];
         let path2 = vec![1, -5, -7, -2, 6];
-        let mut segment_lengths: HashMap<i32, i32> = HashMap::new();
-        for i in 0..10 {
-            segment_lengths.insert(
-                i,
-                if (path1.contains(&i) || path1.contains(&(-i)))
-                    && (path2.contains(&i) || path2.contains(&(-i)))
-                {
-                    100
-                } else {
-                    10
-                },
-            );
-        }
+        let graph = make_test_graph(&path1, &path2, 9);
 
-        let alignments1 =
-            align_paths(&path1, &path2, &segment_lengths, 10000, 1000, 100000).unwrap();
+        let alignments1 = align_paths(
+            &CompressedSteps::from(path1.as_slice()),
+            &CompressedSteps::from(path2.as_slice()),
+            &graph,
+            test_options(10000, None),
+        )
+        .unwrap();
         assert_eq!(alignments1[0].alignment_path1, vec![2, 3, 4, 5]);
         assert_eq!(alignments1[0].alignment_path2, vec![-5, -7, -2]);
         assert_eq!(alignments1[0].path1_start_index, 1);
         assert_eq!(alignments1[0].path1_end_index, 4);
+        assert_eq!(alignments1[0].path2_start_index, 1);
+        assert_eq!(alignments1[0].path2_end_index, 3);
+        assert_eq!(alignments1[0].algorithm, AlignmentAlgorithm::Highmem);
+        assert_eq!(alignments1[0].subproblem_size, (4, 3));
 
         let path3 = vec![1, 2, 3, 4, 5, 6, 7];
         let path4 = vec![1, -3, -2, 4, -6, -5, 7];
-        let alignments2 =
-            align_paths(&path3, &path4, &segment_lengths, 10000, 1000, 100000).unwrap();
+        let alignments2 = align_paths(
+            &CompressedSteps::from(path3.as_slice()),
+            &CompressedSteps::from(path4.as_slice()),
+            &graph,
+            test_options(10000, None),
+        )
+        .unwrap();
         assert_eq!(alignments2[0].alignment_path1, vec![2, 3]);
         assert_eq!(alignments2[0].alignment_path2, vec![-3, -2]);
         assert_eq!(alignments2[0].path1_start_index, 1);
@@ -379,11 +936,55 @@ mod tests {
         assert_eq!(alignments2[1].alignment_path2, vec![-6, -5]);
         assert_eq!(alignments2[1].path1_start_index, 4);
         assert_eq!(alignments2[1].path1_end_index, 5);
+        assert_eq!(alignments2[1].path2_start_index, 4);
+        assert_eq!(alignments2[1].path2_end_index, 5);
+
+        // the lowmem algorithm should find the same alignments on such easy problems
+        let alignments2_lowmem = align_paths(
+            &CompressedSteps::from(path3.as_slice()),
+            &CompressedSteps::from(path4.as_slice()),
+            &graph,
+            test_options(0, None),
+        )
+        .unwrap();
+        for (alignment, alignment_lowmem) in alignments2.iter().zip(&alignments2_lowmem) {
+            assert_eq!(alignment_lowmem.algorithm, AlignmentAlgorithm::Lowmem);
+            assert_eq!(alignment_lowmem.score, alignment.score);
+            assert_eq!(
+                alignment_lowmem.path2_start_index,
+                alignment.path2_start_index
+            );
+            assert_eq!(alignment_lowmem.path2_end_index, alignment.path2_end_index);
+        }
+
+        // and the exact lowmem algorithm always finds the same alignments
+        let alignments2_exact = align_paths(
+            &CompressedSteps::from(path3.as_slice()),
+            &CompressedSteps::from(path4.as_slice()),
+            &graph,
+            AlignmentOptions {
+                exact_lowmem: true,
+                ..test_options(0, None)
+            },
+        )
+        .unwrap();
+        assert_eq!(alignments2_exact.len(), alignments2.len());
+        for (alignment, alignment_exact) in alignments2.iter().zip(&alignments2_exact) {
+            assert_eq!(alignment_exact.algorithm, AlignmentAlgorithm::Hirschberg);
+            assert_eq!(alignment_exact.alignment_path1, alignment.alignment_path1);
+            assert_eq!(alignment_exact.alignment_path2, alignment.alignment_path2);
+            assert_eq!(alignment_exact.score, alignment.score);
+        }
 
         let path5 = vec![1, 2, 3, 4, 5, 6, 7];
         let path6 = vec![1, -3, -2, 8, -6, -5, 7];
-        let alignments3 =
-            align_paths(&path5, &path6, &segment_lengths, 10000, 1000, 100000).unwrap();
+        let alignments3 = align_paths(
+            &CompressedSteps::from(path5.as_slice()),
+            &CompressedSteps::from(path6.as_slice()),
+            &graph,
+            test_options(10000, None),
+        )
+        .unwrap();
         assert_eq!(alignments3[0].alignment_path1, vec![2, 3]);
         assert_eq!(alignments3[0].alignment_path2, vec![-3, -2]);
         assert_eq!(alignments3[0].path1_start_index, 1);
@@ -393,4 +994,186 @@ mod tests {
         assert_eq!(alignments3[1].path1_start_index, 4);
         assert_eq!(alignments3[1].path1_end_index, 5);
     }
+
+    #[test]
+    fn test_align_paths_exact_lowmem_long() {
+        let path1 = vec![1, 2, 3, 4, 5, 6];
+        let path2 = vec![1, -5, -7, -2, 6];
+        let graph = make_test_graph(&path1, &path2, 9);
+        let options = AlignmentOptions {
+            max_path_length: 2,
+            ..test_options(0, None)
+        };
+
+        // the subproblem is longer than the maximum path length, so it's skipped, without any
+        // part of it being aligned as a shorter subproblem
+        let alignments = align_paths(
+            &CompressedSteps::from(path1.as_slice()),
+            &CompressedSteps::from(path2.as_slice()),
+            &graph,
+            options,
+        )
+        .unwrap();
+        assert!(alignments.is_empty());
+
+        // unless it can be aligned exactly in linear memory
+        let alignments_exact = align_paths(
+            &CompressedSteps::from(path1.as_slice()),
+            &CompressedSteps::from(path2.as_slice()),
+            &graph,
+            AlignmentOptions {
+                exact_lowmem: true,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(alignments_exact.len(), 1);
+        assert_eq!(
+            alignments_exact[0].algorithm,
+            AlignmentAlgorithm::Hirschberg
+        );
+        assert_eq!(alignments_exact[0].alignment_path1, vec![2, 3, 4, 5]);
+        assert_eq!(alignments_exact[0].alignment_path2, vec![-5, -7, -2]);
+        assert_eq!(alignments_exact[0].subproblem_size, (4, 3));
+    }
+
+    #[test]
+    fn test_align_paths_local() {
+        // the two inversions are in the same subproblem, separated by a long segment that isn't
+        // shared by the paths
+        let path1 = vec![1, 2, 3, 10, 4, 5, 6, 7];
+        let path2 = vec![1, -6, -5, -4, 11, -3, -2, 7];
+        let mut segment_lengths = vec![100; 9];
+        segment_lengths.extend([1000, 1000]);
+        let graph = PangenomeGraph::from_segment_lengths(segment_lengths, Vec::new()).unwrap();
+        let path1 = CompressedSteps::from(path1);
+        let path2 = CompressedSteps::from(path2);
+
+        // only the best alignment of the subproblem is found, and then only part of the other
+        // inversion is found in the subproblem that's left
+        let alignments = align_paths(&path1, &path2, &graph, test_options(10000, None)).unwrap();
+        assert_eq!(alignments.len(), 2);
+        assert_eq!(alignments[0].alignment_path1, vec![4, 5, 6]);
+        assert_eq!(alignments[1].alignment_path1, vec![3]);
+
+        for max_highmem_length in [10000, 0] {
+            let alignments = align_paths(
+                &path1,
+                &path2,
+                &graph,
+                test_options(max_highmem_length, Some(150)),
+            )
+            .unwrap();
+            assert_eq!(alignments.len(), 2);
+            assert_eq!(alignments[0].alignment_path1, vec![4, 5, 6]);
+            assert_eq!(alignments[0].alignment_path2, vec![-6, -5, -4]);
+            assert_eq!(alignments[0].score, 300);
+            assert_eq!(
+                (
+                    alignments[0].path2_start_index,
+                    alignments[0].path2_end_index
+                ),
+                (1, 3)
+            );
+            assert_eq!(alignments[1].alignment_path1, vec![2, 3]);
+            assert_eq!(alignments[1].alignment_path2, vec![-3, -2]);
+            assert_eq!(alignments[1].score, 200);
+            assert_eq!(
+                (
+                    alignments[1].path1_start_index,
+                    alignments[1].path1_end_index
+                ),
+                (1, 2)
+            );
+            assert_eq!(
+                (
+                    alignments[1].path2_start_index,
+                    alignments[1].path2_end_index
+                ),
+                (5, 6)
+            );
+            assert_eq!(alignments[1].subproblem_size, (6, 6));
+        }
+
+        // alignments scoring less than the threshold aren't reported
+        let alignments =
+            align_paths(&path1, &path2, &graph, test_options(10000, Some(250))).unwrap();
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(alignments[0].alignment_path1, vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn test_align_paths_scoring_scheme() {
+        // same as in test_align_paths_local, but penalizing the long segments between the two
+        // inversions less, so that they're aligned together
+        let path1 = CompressedSteps::from(vec![1, 2, 3, 10, 4, 5, 6, 7]);
+        let path2 = CompressedSteps::from(vec![1, -6, -5, -4, 11, -3, -2, 7]);
+        let mut segment_lengths = vec![100; 9];
+        segment_lengths.extend([1000, 1000]);
+        let graph = PangenomeGraph::from_segment_lengths(segment_lengths, Vec::new()).unwrap();
+        let scoring_scheme = Sublinear { exponent: 0.5 };
+        for max_highmem_path_length in [10000, 0] {
+            let alignment_options = AlignmentOptions {
+                scoring_scheme: &scoring_scheme,
+                ..test_options(max_highmem_path_length, None)
+            };
+            let alignments = align_paths(&path1, &path2, &graph, alignment_options).unwrap();
+            assert_eq!(alignments.len(), 1);
+            assert_eq!(alignments[0].alignment_path1, vec![2, 3, 10, 4, 5, 6]);
+            assert_eq!(alignments[0].alignment_path2, vec![-6, -5, -4, 11, -3, -2]);
+            assert_eq!(alignments[0].score, 500 - 2 * 32);
+        }
+    }
+
+    #[test]
+    fn test_align_paths_gap_penalties() {
+        // an insertion of three segments in path2 between two parts of an inversion
+        let path1 = CompressedSteps::from(vec![1, 2, 3, 4, 5, 6, 7]);
+        let path2 = CompressedSteps::from(vec![1, -6, -5, -4, 11, 12, 13, -3, -2, 7]);
+        let mut segment_lengths = vec![100; 10];
+        segment_lengths.extend([300, 300, 300]);
+        let graph = PangenomeGraph::from_segment_lengths(segment_lengths, Vec::new()).unwrap();
+
+        for max_highmem_path_length in [10000, 0] {
+            // with linear gap penalties, the insertion splits the inversion in two
+            let alignments = align_paths(
+                &path1,
+                &path2,
+                &graph,
+                test_options(max_highmem_path_length, None),
+            )
+            .unwrap();
+            assert_eq!(alignments[0].alignment_path1, vec![4, 5, 6]);
+            assert_eq!(alignments[0].score, 300);
+
+            // but a single run of unaligned segments is cheap with affine gap penalties
+            let alignment_options = AlignmentOptions {
+                gap_penalties: GapPenalties::new(100, 0.1).unwrap(),
+                ..test_options(max_highmem_path_length, None)
+            };
+            let alignments = align_paths(&path1, &path2, &graph, alignment_options).unwrap();
+            assert_eq!(alignments.len(), 1);
+            assert_eq!(alignments[0].alignment_path1, vec![2, 3, 4, 5, 6]);
+            assert_eq!(
+                alignments[0].alignment_path2,
+                vec![-6, -5, -4, 11, 12, 13, -3, -2]
+            );
+            assert_eq!(alignments[0].score, 500 - (100 + 3 * 30));
+            assert_eq!(
+                (
+                    alignments[0].path2_start_index,
+                    alignments[0].path2_end_index
+                ),
+                (1, 8)
+            );
+
+            // unless it costs too much to open a gap
+            let alignment_options = AlignmentOptions {
+                gap_penalties: GapPenalties::new(1000, 0.1).unwrap(),
+                ..test_options(max_highmem_path_length, None)
+            };
+            let alignments = align_paths(&path1, &path2, &graph, alignment_options).unwrap();
+            assert_eq!(alignments[0].alignment_path1, vec![4, 5, 6]);
+        }
+    }
 }
@@ -1,132 +1,1033 @@
-use std::{collections::HashMap, error::Error};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    io::{self, Write},
+};
 
-use log::info;
+use log::{info, warn};
 
-use crate::{InversionError, align, gfa};
+use crate::InversionError;
+use crate::align::{self, AlignmentAlgorithm};
+use crate::graph::{GraphPath, PangenomeGraph};
+use crate::pansn::PanSnName;
+use crate::path_filter::PathFilter;
 
-pub struct AlignmentOptions {
-    pub max_highmem_path_length: usize,
-    pub max_lowmem_drop: usize,
-    pub max_path_length: usize,
+pub use crate::align::{AlignmentOptions, AlignmentStrategy};
+
+/// An inversion found by aligning a query path to a reference path
+#[derive(Debug, Clone, PartialEq)]
+pub struct InversionCall {
+    /// key of the reference path
+    pub ref_path_key: String,
+
+    /// key of the query path
+    pub query_path_key: String,
+
+    /// start and end positions of the inversion on the reference, in 1-based bp coordinates
+    pub ref_interval: (i64, i64),
+
+    /// start and end positions of the inversion on the query, in 1-based bp coordinates of the
+    /// query path in its own orientation, even if it was reversed to align it
+    pub query_interval: (i64, i64),
+
+    /// alignment score of the inverted region
+    pub score: i64,
+
+    /// segments of the reference path in the inversion
+    pub ref_segments: Vec<i64>,
+
+    /// segments of the query path in the inversion, in the orientation of the query path
+    pub query_segments: Vec<i64>,
+
+    /// number of segments of the reference and query paths in the alignment subproblem that the
+    /// inversion was found in
+    pub subproblem_size: (usize, usize),
+
+    /// algorithm used to align the subproblem
+    pub algorithm: AlignmentAlgorithm,
+}
+
+/// A chromosome to look for inversions in, and the paths that represent it
+pub struct ChromosomeGroup {
+    /// name of the chromosome, e.g., `chr3`
+    pub chromosome: String,
+
+    /// key of the path to use as the reference for this chromosome
+    pub ref_path_key: String,
+
+    /// keys of the paths to align to the reference for this chromosome
+    pub query_path_keys: Vec<String>,
+
+    /// keys of the query paths that are in the opposite orientation to the reference, and so need
+    /// to be reversed before aligning
+    pub reverse_query_path_keys: HashSet<String>,
 }
 
-/// Align every non-reference path to the reference.
+/// The inversions found on a single chromosome
+pub struct ChromosomeInversions {
+    /// name of the chromosome, e.g., `chr3`
+    pub chromosome: String,
+
+    /// inversions found in the query paths of this chromosome
+    pub inversions: Vec<InversionCall>,
+
+    /// keys of the paths which were actually aligned to the reference for this chromosome
+    pub query_path_keys: Vec<String>,
+}
+
+impl ChromosomeInversions {
+    /// Add the inversions found in a query path of this chromosome.
+    fn add(&mut self, query_inversions: QueryInversions) {
+        self.query_path_keys.push(query_inversions.query_path_key);
+        self.inversions.extend(query_inversions.inversions);
+    }
+}
+
+/// Decide which paths to align to which reference paths.
+///
+/// If `ref_path` is the name of a path, only that path is used as a reference. Otherwise, it is
+/// taken to be the name of a haplotype (e.g., `sampleA#1`) or of a sample with only one haplotype,
+/// and the first path of that haplotype for each contig is used as the reference for that contig
+/// (see `PangenomeGraph::reference_paths`). Subpath ranges (e.g., `chr1:1000000-2000000`) are
+/// ignored when matching names, so a region extracted from a graph is treated as part of its
+/// chromosome. When there are several reference paths, each query path is aligned to the reference
+/// path with the same contig name, so whole-genome graphs with PanSN path names (e.g.,
+/// `sampleA#1#chr3`) can be run all at once. When there is only one reference path, every other
+/// path is aligned to it, regardless of its name.
+///
+/// Assemblies are often split into several contigs per chromosome, with names like
+/// `sampleB#1#chr1_ctg3` that don't match any reference contig. Each such query path is aligned to
+/// the reference path with which it shares the most sequence, and all of the contigs of a haplotype
+/// are reported together. Contigs can also be assembled in the opposite orientation to the
+/// reference, so any query path sharing more sequence with the reverse strand of its reference path
+/// than with the forward strand is reverse-complemented before alignment rather than being called
+/// as one big inversion.
 ///
 /// # Arguments
 ///
-/// * `segment_lengths`: a map of segment ID to segment length in bp
-/// * `paths`: map of path name to path, represented as sequence of path IDs
-/// * `path_names`: keys of `paths`, in the order they should be accessed
-/// * `paths_to_exclude`: keys of all paths that should not be aligned
-/// * `ref_path_key`: key in `paths` of the reference path
+/// * `graph`: the graph containing the paths
+/// * `ref_path`: name of the reference path or reference sample
+/// * `filter`: which paths to align, e.g., made by `PathFilter::parse` from the paths the user
+///   wants to exclude, so that patterns like `HG0*` mean the same thing as when reading the GFA
+///
+/// # Returns
+///
+/// * a chromosome group for each reference path, in the order the reference paths appear in
+///   the graph
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::alignment_interface;
+/// use inversion_finder::gfa::PathMetadata;
+/// use inversion_finder::graph::{GraphPath, PangenomeGraph};
+/// use inversion_finder::path_filter::PathFilter;
+///
+/// let paths = [
+///     ("a#1#chr1", vec![1, 2]),
+///     ("a#1#chr2", vec![3, 4]),
+///     ("b#1#chr2", vec![3, 4]),
+///     ("b#1#ctg1", vec![-2, -1]),
+///     ("c#1#chr1", vec![1, 2]),
+/// ]
+/// .into_iter()
+/// .map(|(name, steps)| GraphPath::new(name, steps, PathMetadata::default()))
+/// .collect();
+/// let graph = PangenomeGraph::from_segment_lengths(vec![100; 4], paths).unwrap();
+/// let filter = PathFilter::parse(&[], &["c"]).unwrap();
+/// let groups = alignment_interface::group_paths_by_chromosome(&graph, "a", &filter).unwrap();
+/// assert_eq!(groups.len(), 2);
+/// assert_eq!(groups[0].query_path_keys, vec!["b#1#ctg1"]);
+/// assert!(groups[0].reverse_query_path_keys.contains("b#1#ctg1"));
+/// assert_eq!(groups[1].chromosome, "chr2");
+/// assert_eq!(groups[1].ref_path_key, "a#1#chr2");
+/// assert_eq!(groups[1].query_path_keys, vec!["b#1#chr2"]);
+/// ```
+pub fn group_paths_by_chromosome(
+    graph: &PangenomeGraph,
+    ref_path: &str,
+    filter: &PathFilter,
+) -> Result<Vec<ChromosomeGroup>, InversionError> {
+    let ref_paths: Vec<&GraphPath> = graph.reference_paths(ref_path)?;
+
+    let mut groups: Vec<ChromosomeGroup> = ref_paths
+        .iter()
+        .map(|ref_path| ChromosomeGroup {
+            chromosome: ref_path.pansn_name().contig.to_string(),
+            ref_path_key: ref_path.name().to_string(),
+            query_path_keys: Vec::new(),
+            reverse_query_path_keys: HashSet::new(),
+        })
+        .collect();
+
+    // map of segment ID to the index of the reference path it is in and its orientation there.
+    // Segments that are traversed in both orientations by a reference path don't tell us anything
+    // about orientation, so those are left out.
+    let mut ref_segments: HashMap<i64, (usize, i64)> = HashMap::new();
+    let mut ambiguous_segments: HashSet<i64> = HashSet::new();
+    for (group_index, ref_path) in ref_paths.iter().enumerate() {
+        for segment in ref_path.steps() {
+            match ref_segments.get(&segment.abs()) {
+                Some((_, sign)) if *sign != segment.signum() => {
+                    ambiguous_segments.insert(segment.abs());
+                }
+                Some(_) => {}
+                None => {
+                    ref_segments.insert(segment.abs(), (group_index, segment.signum()));
+                }
+            }
+        }
+    }
+    for segment in ambiguous_segments {
+        ref_segments.remove(&segment);
+    }
+
+    for path in graph.paths() {
+        if ref_paths
+            .iter()
+            .any(|ref_path| std::ptr::eq(*ref_path, path))
+            || !filter.keeps(path.name())
+        {
+            continue;
+        }
+        let path_name = path.name();
+
+        // count up how many bp this path shares with each reference path in each orientation
+        let mut shared_bp = vec![(0, 0); groups.len()];
+        for segment in path.steps() {
+            if let Some((group_index, ref_sign)) = ref_segments.get(&segment.abs()) {
+                let length = graph.segment_length(segment)?;
+                if segment.signum() == *ref_sign {
+                    shared_bp[*group_index].0 += length;
+                } else {
+                    shared_bp[*group_index].1 += length;
+                }
+            }
+        }
+
+        let contig = path.pansn_name().contig;
+        let same_contig_groups: Vec<usize> = (0..groups.len())
+            .filter(|i| groups[*i].chromosome == contig)
+            .collect();
+        let group_index = if groups.len() == 1 {
+            Some(0)
+        } else if same_contig_groups.len() == 1 {
+            Some(same_contig_groups[0])
+        } else {
+            (0..groups.len())
+                .filter(|i| same_contig_groups.is_empty() || same_contig_groups.contains(i))
+                .filter(|i| shared_bp[*i].0 + shared_bp[*i].1 > 0)
+                .max_by_key(|i| shared_bp[*i].0 + shared_bp[*i].1)
+        };
+
+        match group_index {
+            Some(group_index) => {
+                let group = &mut groups[group_index];
+                if contig != group.chromosome {
+                    info!(
+                        "Assigning path {} to chromosome {}",
+                        path_name, group.chromosome
+                    );
+                }
+                let (forward_bp, reverse_bp) = shared_bp[group_index];
+                if reverse_bp > forward_bp {
+                    info!(
+                        "Path {} is in the opposite orientation to {}, so reversing it",
+                        path_name, group.ref_path_key
+                    );
+                    group.reverse_query_path_keys.insert(path_name.to_string());
+                }
+                group.query_path_keys.push(path_name.to_string());
+            }
+            None => warn!(
+                "Skipping path {} because it doesn't share any sequence with a reference path",
+                path_name
+            ),
+        }
+    }
+
+    Ok(groups)
+}
+
+/// The inversions found in a single query path
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryInversions {
+    /// name of the chromosome the query path was aligned on, e.g., `chr3`
+    pub chromosome: String,
+
+    /// key of the query path
+    pub query_path_key: String,
+
+    /// inversions found in the query path
+    pub inversions: Vec<InversionCall>,
+}
+
+/// Iterator that aligns the query paths of a chromosome to its reference path one at a time,
+/// yielding the inversions in each query path as soon as it has been aligned.
+///
+/// Made by `align_queries`.
+pub struct QueryAlignments<'a> {
+    graph: &'a PangenomeGraph,
+    chromosome_group: &'a ChromosomeGroup,
+    alignment_options: AlignmentOptions<'a>,
+
+    /// index in `chromosome_group.query_path_keys` of the next query path to align
+    next_query: usize,
+}
+
+impl Iterator for QueryAlignments<'_> {
+    type Item = Result<QueryInversions, InversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let query_path_key = self.chromosome_group.query_path_keys.get(self.next_query)?;
+        self.next_query += 1;
+        Some(
+            align_query(
+                self.graph,
+                self.chromosome_group,
+                query_path_key,
+                self.alignment_options,
+            )
+            .map(|inversions| QueryInversions {
+                chromosome: self.chromosome_group.chromosome.clone(),
+                query_path_key: query_path_key.clone(),
+                inversions,
+            }),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.chromosome_group.query_path_keys.len() - self.next_query;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Align the query paths of a chromosome to its reference path lazily, one query path per
+/// iteration, so that the results for each query path can be used as soon as they're ready
+/// instead of waiting for the whole chromosome.
+///
+/// # Arguments
+///
+/// * `graph`: the graph containing the paths
+/// * `chromosome_group`: the reference path of the chromosome and the query paths to align to it
+/// * `alignment_options`: parameters for the alignments
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::alignment_interface::{self, AlignmentOptions, AlignmentStrategy};
+/// use inversion_finder::gfa::PathMetadata;
+/// use inversion_finder::graph::{GraphPath, PangenomeGraph};
+/// use inversion_finder::path_filter::PathFilter;
+/// use inversion_finder::scoring::{GapPenalties, SegmentLength};
+///
+/// let paths = [("a", vec![1, 2, 3, 4]), ("b", vec![1, -3, -2, 4]), ("c", vec![1, 2, 3, 4])]
+///     .into_iter()
+///     .map(|(name, steps)| GraphPath::new(name, steps, PathMetadata::default()))
+///     .collect();
+/// let graph = PangenomeGraph::from_segment_lengths(vec![100; 4], paths).unwrap();
+/// let no_filter = PathFilter::default();
+/// let groups = alignment_interface::group_paths_by_chromosome(&graph, "a", &no_filter).unwrap();
+/// let alignment_options = AlignmentOptions {
+///     max_highmem_path_length: 10000,
+///     max_lowmem_drop: 1000,
+///     exact_lowmem: false,
+///     max_path_length: 100000,
+///     min_alignment_score: None,
+///     scoring_scheme: &SegmentLength,
+///     gap_penalties: GapPenalties::default(),
+///     strategy: AlignmentStrategy::DynamicProgramming,
+///     refine_window: None,
+/// };
+/// let mut query_alignments =
+///     alignment_interface::align_queries(&graph, &groups[0], alignment_options);
+/// let query_inversions = query_alignments.next().unwrap().unwrap();
+/// assert_eq!(query_inversions.query_path_key, "b");
+/// assert_eq!(query_inversions.inversions[0].ref_interval, (101, 300));
+/// assert!(query_alignments.next().unwrap().unwrap().inversions.is_empty());
+/// assert!(query_alignments.next().is_none());
+/// ```
+pub fn align_queries<'a>(
+    graph: &'a PangenomeGraph,
+    chromosome_group: &'a ChromosomeGroup,
+    alignment_options: AlignmentOptions<'a>,
+) -> QueryAlignments<'a> {
+    QueryAlignments {
+        graph,
+        chromosome_group,
+        alignment_options,
+        next_query: 0,
+    }
+}
+
+/// Align every query path of a chromosome to its reference path.
+///
+/// This waits for all of the query paths to be aligned; use `align_queries` to get the results for
+/// each query path as soon as it's done.
+///
+/// # Arguments
+///
+/// * `graph`: the graph containing the paths
+/// * `chromosome_group`: the reference path of the chromosome and the query paths to align to it
 /// * `alignment_options`: parameters for the alignments
 ///
 /// # Returns
 ///
-/// * `inversions`: a vec of tuples (query path key, node ID of first node in inverison, node ID of
-///   last node in inversion)
+/// * `inversions`: the inversions found in every query path
 /// * `query_path_keys`: a vec of keys for paths which were actually aligned to the reference
 pub fn align_all_queries(
-    segment_lengths: &HashMap<i32, i32>,
-    paths: &HashMap<String, Vec<i32>>,
-    path_names: &[String],
-    paths_to_exclude: &[&str],
-    ref_path_key: &str,
+    graph: &PangenomeGraph,
+    chromosome_group: &ChromosomeGroup,
     alignment_options: AlignmentOptions,
-) -> Result<(Vec<(String, i32, i32)>, Vec<String>), InversionError> {
+) -> Result<(Vec<InversionCall>, Vec<String>), InversionError> {
     let mut query_path_keys = Vec::<String>::new();
-    let mut inversions = Vec::<(String, i32, i32)>::new();
-    let ref_path = paths
-        .get(ref_path_key)
-        .ok_or(InversionError::PathNotFound(ref_path_key.to_string()))?
-        .clone();
-
-    for query_path_key in path_names {
-        if query_path_key != ref_path_key
-            && !paths_to_exclude
-                .iter()
-                .any(|x| x == query_path_key || *x == query_path_key.split("#").nth(0).unwrap())
-        {
-            info!("Starting alignment of path {}", query_path_key);
-            let query_path = paths
-                .get(query_path_key)
-                .ok_or(InversionError::PathNotFound(query_path_key.to_string()))?;
-            query_path_keys.push(query_path_key.clone());
-            let alignments = align::align_paths(
-                &ref_path,
-                &query_path,
-                &segment_lengths,
-                alignment_options.max_highmem_path_length,
-                alignment_options.max_lowmem_drop,
-                alignment_options.max_path_length,
-            )?;
+    let mut inversions = Vec::<InversionCall>::new();
+    for query_inversions in align_queries(graph, chromosome_group, alignment_options) {
+        let query_inversions = query_inversions?;
+        query_path_keys.push(query_inversions.query_path_key);
+        inversions.extend(query_inversions.inversions);
+    }
+    Ok((inversions, query_path_keys))
+}
 
-            // make a list of segments that we need to find the positions of
-            let mut segments_to_lookup = Vec::new();
-            for alignment in &alignments {
-                segments_to_lookup.push(alignment.path1_start_index);
-                segments_to_lookup.push(alignment.path1_end_index);
-            }
-            let base_positions =
-                gfa::lookup_base_positions(&ref_path, &segment_lengths, &segments_to_lookup)?;
+/// Align a single query path of a chromosome to its reference path.
+fn align_query(
+    graph: &PangenomeGraph,
+    chromosome_group: &ChromosomeGroup,
+    query_path_key: &str,
+    alignment_options: AlignmentOptions,
+) -> Result<Vec<InversionCall>, InversionError> {
+    info!("Starting alignment of path {}", query_path_key);
+    let ref_path = graph.get_path(&chromosome_group.ref_path_key)?;
+    let query_path = graph.get_path(query_path_key)?;
+    let reversed = chromosome_group
+        .reverse_query_path_keys
+        .contains(query_path_key);
+    let query_steps = if reversed {
+        query_path.reversed_steps()
+    } else {
+        query_path.steps().clone()
+    };
+    let alignments = align::align_paths(ref_path.steps(), &query_steps, graph, alignment_options)?;
+
+    let step_not_found = |path_name: &str, step: usize| {
+        InversionError::InvalidAlignment(format!(
+            "step {} is past the end of path {}",
+            step, path_name
+        ))
+    };
+
+    // indices of the aligned query segments in the query path in its own orientation
+    let query_indices = |alignment: &align::Alignment| {
+        if reversed {
+            let reverse_index = |index: usize| {
+                (query_steps.len() - 1)
+                    .checked_sub(index)
+                    .ok_or_else(|| step_not_found(query_path_key, index))
+            };
+            Ok((
+                reverse_index(alignment.path2_end_index)?,
+                reverse_index(alignment.path2_start_index)?,
+            ))
+        } else {
+            Ok((alignment.path2_start_index, alignment.path2_end_index))
+        }
+    };
+
+    // the reference path's index is kept by the graph to share with the other queries, but each
+    // query path is only aligned once, so only the positions of its aligned steps are looked up
+    let ref_position_index = graph.position_index(ref_path.name())?;
+    let ref_step_interval = |step| {
+        ref_position_index
+            .step_interval(step)
+            .ok_or_else(|| step_not_found(ref_path.name(), step))
+    };
+    let ref_interval = |start_step, end_step| -> Result<(i64, i64), InversionError> {
+        let (start, _) = ref_step_interval(start_step)?;
+        let (_, end) = ref_step_interval(end_step)?;
+        Ok((start, end))
+    };
+    let mut query_steps_to_look_up = Vec::with_capacity(2 * alignments.len());
+    for alignment in &alignments {
+        let (start, end) = query_indices(alignment)?;
+        query_steps_to_look_up.extend([start, end]);
+    }
+    let query_step_intervals = graph.step_intervals(query_path, &query_steps_to_look_up)?;
+
+    let mut inversions = Vec::<InversionCall>::new();
+    for ((alignment, query_step_intervals), query_steps) in alignments
+        .into_iter()
+        .zip(query_step_intervals.chunks(2))
+        .zip(query_steps_to_look_up.chunks(2))
+    {
+        let query_step_interval = |i: usize| {
+            query_step_intervals[i].ok_or_else(|| step_not_found(query_path_key, query_steps[i]))
+        };
+        let query_segments = if reversed {
+            alignment.alignment_path2.iter().rev().map(|x| -x).collect()
+        } else {
+            alignment.alignment_path2
+        };
+        inversions.push(InversionCall {
+            ref_path_key: ref_path.name().to_string(),
+            query_path_key: query_path_key.to_string(),
+            ref_interval: ref_interval(alignment.path1_start_index, alignment.path1_end_index)?,
+            query_interval: (query_step_interval(0)?.0, query_step_interval(1)?.1),
+            score: alignment.score,
+            ref_segments: alignment.alignment_path1,
+            query_segments,
+            subproblem_size: alignment.subproblem_size,
+            algorithm: alignment.algorithm,
+        });
+    }
+    Ok(inversions)
+}
+
+/// Receives the inversions found in each query path as soon as it has been aligned, so that they
+/// can be used or saved as the alignments go rather than only once every path has been aligned.
+pub trait InversionSink {
+    /// Take the inversions found in a query path.
+    fn add_query(&mut self, query_inversions: QueryInversions) -> Result<(), Box<dyn Error>>;
+
+    /// Called once every query path of a chromosome has been aligned and given to `add_query`.
+    fn finish_chromosome(&mut self, _chromosome: &ChromosomeGroup) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
 
-            for alignment in alignments {
-                let start_position = base_positions.get(&alignment.path1_start_index).unwrap().0;
-                let end_position = base_positions.get(&alignment.path1_end_index).unwrap().1;
-                inversions.push((query_path_key.clone(), start_position, end_position));
+/// Align the query paths of each chromosome to its reference path, giving the inversions found in
+/// each query path to `sink` as soon as it has been aligned.
+///
+/// If aligning a query path fails, the inversions found up until then have already been given to
+/// `sink`, so nothing that was finished is lost.
+///
+/// # Arguments
+///
+/// * `graph`: the graph containing the paths
+/// * `chromosome_groups`: the reference path and query paths of each chromosome, as made by
+///   `group_paths_by_chromosome`
+/// * `alignment_options`: parameters for the alignments
+/// * `sink`: what to give the inversions to
+pub fn align_chromosomes(
+    graph: &PangenomeGraph,
+    chromosome_groups: &[ChromosomeGroup],
+    alignment_options: AlignmentOptions,
+    sink: &mut impl InversionSink,
+) -> Result<(), Box<dyn Error>> {
+    for chromosome_group in chromosome_groups {
+        info!(
+            "Aligning {} paths to {} for chromosome {}",
+            chromosome_group.query_path_keys.len(),
+            chromosome_group.ref_path_key,
+            chromosome_group.chromosome
+        );
+        for query_inversions in align_queries(graph, chromosome_group, alignment_options) {
+            let query_inversions = query_inversions?;
+            info!(
+                "Found {} inversions in path {}",
+                query_inversions.inversions.len(),
+                query_inversions.query_path_key
+            );
+            sink.add_query(query_inversions)?;
+        }
+        sink.finish_chromosome(chromosome_group)?;
+    }
+    Ok(())
+}
+
+/// Collects the inversions found in each query path as they arrive, keeping all of them in memory
+/// so that they can be written as a table once every query path has been aligned.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::alignment_interface::{InversionCollator, QueryInversions};
+///
+/// let mut collator = InversionCollator::new();
+/// collator.add(QueryInversions {
+///     chromosome: "chr1".to_string(),
+///     query_path_key: "b#1#chr1".to_string(),
+///     inversions: Vec::new(),
+/// });
+/// let mut table = Vec::new();
+/// collator.write_table(&mut table, 50).unwrap();
+/// assert_eq!(String::from_utf8(table).unwrap(), "ref\tstart\tend\tb#1\n");
+/// ```
+#[derive(Default)]
+pub struct InversionCollator {
+    chromosome_inversions: Vec<ChromosomeInversions>,
+}
+
+impl InversionCollator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the inversions found in a query path.
+    pub fn add(&mut self, query_inversions: QueryInversions) {
+        let chromosome = match self
+            .chromosome_inversions
+            .iter()
+            .position(|chromosome| chromosome.chromosome == query_inversions.chromosome)
+        {
+            Some(i) => &mut self.chromosome_inversions[i],
+            None => {
+                self.chromosome_inversions.push(ChromosomeInversions {
+                    chromosome: query_inversions.chromosome.clone(),
+                    inversions: Vec::new(),
+                    query_path_keys: Vec::new(),
+                });
+                self.chromosome_inversions.last_mut().unwrap()
             }
+        };
+        chromosome.add(query_inversions);
+    }
+
+    /// The inversions added so far, by chromosome in the order the chromosomes were first seen
+    pub fn chromosome_inversions(&self) -> &[ChromosomeInversions] {
+        &self.chromosome_inversions
+    }
+
+    /// Write a table of the inversions added so far, in the format of
+    /// `print_collated_inversions`.
+    pub fn write_table(
+        &self,
+        out: &mut impl Write,
+        min_inversion_length: i64,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut haplotypes: Vec<&str> = Vec::new();
+        for chromosome in &self.chromosome_inversions {
+            add_haplotypes(&mut haplotypes, &chromosome.query_path_keys);
+        }
+        write_header(out, &haplotypes)?;
+        for chromosome in &self.chromosome_inversions {
+            write_chromosome_rows(out, &haplotypes, chromosome, min_inversion_length)?;
         }
+        Ok(())
+    }
+}
+
+impl InversionSink for InversionCollator {
+    fn add_query(&mut self, query_inversions: QueryInversions) -> Result<(), Box<dyn Error>> {
+        self.add(query_inversions);
+        Ok(())
+    }
+}
+
+/// Writes a table of inversions in the format of `print_collated_inversions` as they're found,
+/// writing and flushing the rows of each chromosome as soon as all of its query paths have been
+/// aligned, so that only the inversions of one chromosome are kept in memory at a time and the
+/// rows of the finished chromosomes are kept even if the run fails later on.
+///
+/// The columns of the table are the haplotypes of the query paths of every chromosome, so they're
+/// taken from the chromosome groups before any path is aligned.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::alignment_interface::{
+///     ChromosomeGroup, InversionSink, InversionTableWriter, QueryInversions,
+/// };
+/// use std::collections::HashSet;
+///
+/// let groups = [ChromosomeGroup {
+///     chromosome: "chr1".to_string(),
+///     ref_path_key: "a#1#chr1".to_string(),
+///     query_path_keys: vec!["b#1#chr1".to_string()],
+///     reverse_query_path_keys: HashSet::new(),
+/// }];
+/// let mut table = Vec::new();
+/// let mut table_writer = InversionTableWriter::new(&mut table, &groups, 50).unwrap();
+/// table_writer
+///     .add_query(QueryInversions {
+///         chromosome: "chr1".to_string(),
+///         query_path_key: "b#1#chr1".to_string(),
+///         inversions: Vec::new(),
+///     })
+///     .unwrap();
+/// table_writer.finish_chromosome(&groups[0]).unwrap();
+/// assert_eq!(String::from_utf8(table).unwrap(), "ref\tstart\tend\tb#1\n");
+/// ```
+pub struct InversionTableWriter<W: Write> {
+    out: W,
+    haplotypes: Vec<String>,
+    min_inversion_length: i64,
+
+    /// inversions found so far on the chromosome that is being aligned
+    chromosome: Option<ChromosomeInversions>,
+}
+
+impl<W: Write> InversionTableWriter<W> {
+    /// Make a table writer and write the header of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `out`: where to write the table
+    /// * `chromosome_groups`: the chromosomes that will be aligned, to get the haplotype columns
+    /// * `min_inversion_length`: minimum length in bp of inversions to write
+    pub fn new(
+        mut out: W,
+        chromosome_groups: &[ChromosomeGroup],
+        min_inversion_length: i64,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut haplotypes: Vec<&str> = Vec::new();
+        for chromosome_group in chromosome_groups {
+            add_haplotypes(&mut haplotypes, &chromosome_group.query_path_keys);
+        }
+        write_header(&mut out, &haplotypes)?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            haplotypes: haplotypes.into_iter().map(String::from).collect(),
+            min_inversion_length,
+            chromosome: None,
+        })
     }
-    Ok((inversions, query_path_keys))
 }
 
+impl<W: Write> InversionSink for InversionTableWriter<W> {
+    fn add_query(&mut self, query_inversions: QueryInversions) -> Result<(), Box<dyn Error>> {
+        self.chromosome
+            .get_or_insert_with(|| ChromosomeInversions {
+                chromosome: query_inversions.chromosome.clone(),
+                inversions: Vec::new(),
+                query_path_keys: Vec::new(),
+            })
+            .add(query_inversions);
+        Ok(())
+    }
+
+    fn finish_chromosome(&mut self, _chromosome: &ChromosomeGroup) -> Result<(), Box<dyn Error>> {
+        if let Some(chromosome) = self.chromosome.take() {
+            let haplotypes: Vec<&str> = self.haplotypes.iter().map(String::as_str).collect();
+            write_chromosome_rows(
+                &mut self.out,
+                &haplotypes,
+                &chromosome,
+                self.min_inversion_length,
+            )?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Find inversions in every chromosome and print them as a table as each chromosome is finished.
+///
+/// There is one column for each haplotype (i.e., PanSN sample and haplotype) that is aligned on
+/// any chromosome, so that all of the contigs of a haplotype are merged into one column. A call is
+/// 1 if the inversion was found in any path of the haplotype, 0 if it wasn't, and "." if the
+/// haplotype has no path that was aligned on that chromosome. The rows of each chromosome are
+/// printed as soon as all of its query paths have been aligned, so if a later chromosome fails,
+/// the ones before it have still been printed.
+///
+/// # Arguments
+///
+/// * `graph`: the graph containing the paths
+/// * `chromosome_groups`: the reference path and query paths of each chromosome
+/// * `alignment_options`: parameters for the alignments
+/// * `min_inversion_length`: minimum length in bp of inversions to print
 pub fn print_collated_inversions(
-    inversions: &[(String, i32, i32)],
-    query_path_keys: &[String],
-    ref_path_key: &str,
-    min_inversion_length: i32,
+    graph: &PangenomeGraph,
+    chromosome_groups: &[ChromosomeGroup],
+    alignment_options: AlignmentOptions,
+    min_inversion_length: i64,
 ) -> Result<(), Box<dyn Error>> {
-    // finally, collate the inversions from the different animals and print out a table
-    let mut inversions_collated: HashMap<(i32, i32), Vec<String>> = HashMap::new();
-    for (path, start_position, end_position) in inversions {
+    let mut table_writer =
+        InversionTableWriter::new(io::stdout().lock(), chromosome_groups, min_inversion_length)?;
+    align_chromosomes(
+        graph,
+        chromosome_groups,
+        alignment_options,
+        &mut table_writer,
+    )
+}
+
+/// Add the haplotypes of some query paths to a list of haplotypes, if they aren't in it already.
+fn add_haplotypes<'a>(haplotypes: &mut Vec<&'a str>, query_path_keys: &'a [String]) {
+    for query_path_key in query_path_keys {
+        let haplotype = PanSnName::parse(query_path_key).haplotype_name();
+        if !haplotypes.contains(&haplotype) {
+            haplotypes.push(haplotype);
+        }
+    }
+}
+
+fn write_header(out: &mut impl Write, haplotypes: &[&str]) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "ref\tstart\tend\t{}", haplotypes.join("\t"))?;
+    Ok(())
+}
+
+/// Write the rows of the table for the inversions found on a chromosome.
+fn write_chromosome_rows(
+    out: &mut impl Write,
+    haplotypes: &[&str],
+    chromosome: &ChromosomeInversions,
+    min_inversion_length: i64,
+) -> Result<(), Box<dyn Error>> {
+    let aligned_haplotypes = HashSet::<&str>::from_iter(
+        chromosome
+            .query_path_keys
+            .iter()
+            .map(|k| PanSnName::parse(k).haplotype_name()),
+    );
+
+    // collate the inversions from the different haplotypes
+    let mut inversions_collated: HashMap<(i64, i64), HashSet<&str>> = HashMap::new();
+    for inversion in &chromosome.inversions {
         inversions_collated
-            .entry((*start_position, *end_position))
-            .and_modify(|v| v.push(path.clone()))
-            .or_insert(vec![path.clone()]);
+            .entry(inversion.ref_interval)
+            .or_default()
+            .insert(PanSnName::parse(&inversion.query_path_key).haplotype_name());
     }
 
     // print the collated inversions out ordered by start position
-    println!("ref\tstart\tend\t{}", query_path_keys.join("\t"));
-    let mut keys: Vec<&(i32, i32)> = inversions_collated.keys().collect();
-    keys.sort_by_key(|k| k.0);
+    let mut keys: Vec<&(i64, i64)> = inversions_collated.keys().collect();
+    keys.sort();
     for (start_position, end_position) in keys {
         if end_position - start_position >= min_inversion_length {
-            let paths = inversions_collated
+            let inverted_haplotypes = inversions_collated
                 .get(&(*start_position, *end_position))
                 .ok_or(format!(
                     "Cannot find inversion {}-{}",
                     *start_position, *end_position
                 ))?;
-            let mut calls = Vec::<i8>::new();
-            for query_path_key in query_path_keys {
-                if paths.contains(query_path_key) {
-                    calls.push(1);
-                } else {
-                    calls.push(0);
-                }
-            }
-            println!(
+            let calls: Vec<&str> = haplotypes
+                .iter()
+                .map(|haplotype| {
+                    if inverted_haplotypes.contains(haplotype) {
+                        "1"
+                    } else if aligned_haplotypes.contains(haplotype) {
+                        "0"
+                    } else {
+                        "."
+                    }
+                })
+                .collect();
+            writeln!(
+                out,
                 "{}\t{}\t{}\t{}",
-                ref_path_key,
+                chromosome.chromosome,
                 start_position,
                 end_position,
-                calls
-                    .iter()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<_>>()
-                    .join("\t"),
-            );
+                calls.join("\t"),
+            )?;
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa;
+    use crate::scoring::{GapPenalties, SegmentLength};
+
+    fn exclude(patterns: &[&str]) -> PathFilter {
+        PathFilter::parse(&[], patterns).unwrap()
+    }
+
+    fn make_test_graph(num_segments: usize, path_list: &[(&str, Vec<i64>)]) -> PangenomeGraph {
+        let paths = path_list
+            .iter()
+            .map(|(k, v)| GraphPath::new(k, v.clone(), gfa::PathMetadata::default()))
+            .collect();
+        PangenomeGraph::from_segment_lengths(vec![100; num_segments], paths).unwrap()
+    }
+
+    #[test]
+    fn test_group_paths_single_reference() {
+        let graph = make_test_graph(
+            3,
+            &[
+                ("a#0#chr1", vec![1, 2, 3]),
+                ("b#0#scaffold_3", vec![1, 2, 3]),
+                ("c#0#chr1", vec![1, 2, 3]),
+                ("d", vec![-3, -2, 1]),
+            ],
+        );
+
+        // with a single reference path, all other paths are aligned to it regardless of name
+        let groups = group_paths_by_chromosome(&graph, "a", &exclude(&["c#0#chr1"])).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].chromosome, "chr1");
+        assert_eq!(groups[0].query_path_keys, vec!["b#0#scaffold_3", "d"]);
+        assert_eq!(
+            groups[0].reverse_query_path_keys,
+            HashSet::from(["d".to_string()])
+        );
+
+        // paths to exclude can be any pattern that can be used to filter paths when reading a GFA
+        let groups = group_paths_by_chromosome(&graph, "a", &exclude(&["c*", "re:^d$"])).unwrap();
+        assert_eq!(groups[0].query_path_keys, vec!["b#0#scaffold_3"]);
+
+        let groups =
+            group_paths_by_chromosome(&graph, "b#0#scaffold_3", &PathFilter::default()).unwrap();
+        assert_eq!(groups[0].ref_path_key, "b#0#scaffold_3");
+        assert_eq!(groups[0].query_path_keys, vec!["a#0#chr1", "c#0#chr1", "d"]);
+
+        assert!(matches!(
+            group_paths_by_chromosome(&graph, "e", &PathFilter::default()),
+            Err(InversionError::ReferenceNotFound(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_group_paths_contigs() {
+        let graph = make_test_graph(
+            9,
+            &[
+                ("a#0#chr1", vec![1, 2, 3, 4]),
+                ("a#0#chr2", vec![5, 6, 7, 8]),
+                ("b#1#ctg1", vec![1, 2]),
+                ("b#1#ctg2", vec![-4, 3, -2]),
+                ("b#1#ctg3", vec![-8, -7, -6]),
+                ("b#2#ctg4", vec![9]),
+            ],
+        );
+        let groups = group_paths_by_chromosome(&graph, "a", &exclude(&["b#2"])).unwrap();
+        assert_eq!(groups[0].query_path_keys, vec!["b#1#ctg1", "b#1#ctg2"]);
+        assert_eq!(
+            groups[0].reverse_query_path_keys,
+            HashSet::from(["b#1#ctg2".to_string()])
+        );
+        assert_eq!(groups[1].query_path_keys, vec!["b#1#ctg3"]);
+        assert_eq!(
+            groups[1].reverse_query_path_keys,
+            HashSet::from(["b#1#ctg3".to_string()])
+        );
+
+        // sample b has two haplotypes, so it can't be the reference without saying which
+        match group_paths_by_chromosome(&graph, "b", &PathFilter::default()) {
+            Err(InversionError::AmbiguousReference(name, candidates)) => {
+                assert_eq!(name, "b");
+                assert_eq!(candidates, vec!["b#1", "b#2"]);
+            }
+            _ => panic!("reference sample b should be ambiguous"),
+        }
+        let groups = group_paths_by_chromosome(&graph, "b#1", &PathFilter::default()).unwrap();
+        assert_eq!(groups.len(), 3);
+    }
+
+    /// Make a graph where b has an inversion relative to a, and c is the reverse complement of b
+    /// starting at position 1000 of its contig.
+    fn make_inversion_test_graph() -> PangenomeGraph {
+        let path = |name, steps, start_offset| {
+            GraphPath::new(
+                name,
+                steps,
+                gfa::PathMetadata {
+                    start_offset,
+                    overlaps: Vec::new(),
+                },
+            )
+        };
+        PangenomeGraph::from_segment_lengths(
+            vec![100, 50, 60, 70, 80, 100],
+            vec![
+                path("a#0#chr1", vec![1, 2, 3, 4, 5, 6], 0),
+                path("b#0#chr1", vec![1, -4, -3, -2, 5, 6], 0),
+                path("c#0#ctg1", vec![-6, -5, 2, 3, 4, -1], 1000),
+            ],
+        )
+        .unwrap()
+    }
+
+    const TEST_ALIGNMENT_OPTIONS: AlignmentOptions = AlignmentOptions {
+        max_highmem_path_length: 10000,
+        max_lowmem_drop: 1000,
+        exact_lowmem: false,
+        max_path_length: 100000,
+        min_alignment_score: None,
+        scoring_scheme: &SegmentLength,
+        gap_penalties: GapPenalties {
+            open: 0,
+            extend_factor: 1.0,
+        },
+        strategy: AlignmentStrategy::DynamicProgramming,
+        refine_window: None,
+    };
+
+    #[test]
+    fn test_align_all_queries() {
+        let graph = make_inversion_test_graph();
+        let groups = group_paths_by_chromosome(&graph, "a", &PathFilter::default()).unwrap();
+        let (inversions, query_path_keys) =
+            align_all_queries(&graph, &groups[0], TEST_ALIGNMENT_OPTIONS).unwrap();
+        assert_eq!(query_path_keys, vec!["b#0#chr1", "c#0#ctg1"]);
+        assert_eq!(inversions.len(), 2);
+
+        assert_eq!(inversions[0].ref_path_key, "a#0#chr1");
+        assert_eq!(inversions[0].ref_interval, (101, 280));
+        assert_eq!(inversions[0].query_interval, (101, 280));
+        assert_eq!(inversions[0].ref_segments, vec![2, 3, 4]);
+        assert_eq!(inversions[0].query_segments, vec![-4, -3, -2]);
+        assert_eq!(inversions[0].algorithm, AlignmentAlgorithm::Highmem);
+
+        // c is reversed to align it, but its calls are still in its own coordinates
+        assert_eq!(inversions[1].ref_interval, (101, 280));
+        assert_eq!(inversions[1].query_interval, (1181, 1360));
+        assert_eq!(inversions[1].query_segments, vec![2, 3, 4]);
+        assert_eq!(inversions[1].score, inversions[0].score);
+    }
+
+    #[test]
+    fn test_collate_streamed_queries() {
+        let graph = make_inversion_test_graph();
+        let groups = group_paths_by_chromosome(&graph, "a", &PathFilter::default()).unwrap();
+        let mut collator = InversionCollator::new();
+        for query_inversions in align_queries(&graph, &groups[0], TEST_ALIGNMENT_OPTIONS) {
+            let query_inversions = query_inversions.unwrap();
+            assert_eq!(query_inversions.chromosome, "chr1");
+            assert_eq!(query_inversions.inversions.len(), 1);
+            collator.add(query_inversions);
+        }
+        // a haplotype aligned on another chromosome only
+        collator.add(QueryInversions {
+            chromosome: "chr2".to_string(),
+            query_path_key: "d#1#chr2".to_string(),
+            inversions: Vec::new(),
+        });
+        assert_eq!(collator.chromosome_inversions()[0].query_path_keys.len(), 2);
+
+        let mut table = Vec::new();
+        collator.write_table(&mut table, 50).unwrap();
+        assert_eq!(
+            String::from_utf8(table).unwrap(),
+            "ref\tstart\tend\tb#0\tc#0\td#1\nchr1\t101\t280\t1\t1\t.\n"
+        );
+        let mut table = Vec::new();
+        collator.write_table(&mut table, 500).unwrap();
+        assert_eq!(
+            String::from_utf8(table).unwrap(),
+            "ref\tstart\tend\tb#0\tc#0\td#1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_table_as_chromosomes_finish() {
+        let graph = make_inversion_test_graph();
+        let mut groups = group_paths_by_chromosome(&graph, "a", &PathFilter::default()).unwrap();
+        // a second chromosome with a query path that isn't in the graph, so aligning it fails
+        groups.push(ChromosomeGroup {
+            chromosome: "chr2".to_string(),
+            ref_path_key: "a#0#chr1".to_string(),
+            query_path_keys: vec!["d#0#chr2".to_string()],
+            reverse_query_path_keys: HashSet::new(),
+        });
+
+        let mut table = Vec::new();
+        let mut table_writer = InversionTableWriter::new(&mut table, &groups, 50).unwrap();
+        let result = align_chromosomes(&graph, &groups, TEST_ALIGNMENT_OPTIONS, &mut table_writer);
+        assert!(result.is_err());
+        // the first chromosome was written before the second one failed
+        assert_eq!(
+            String::from_utf8(table).unwrap(),
+            "ref\tstart\tend\tb#0\tc#0\td#0\nchr1\t101\t280\t1\t1\t.\n"
+        );
+
+        let mut collator = InversionCollator::new();
+        align_chromosomes(&graph, &groups[..1], TEST_ALIGNMENT_OPTIONS, &mut collator).unwrap();
+        assert_eq!(collator.chromosome_inversions()[0].inversions.len(), 2);
+    }
+}
@@ -0,0 +1,681 @@
+//! Binary cache of a parsed GFA, so that a big graph only has to be parsed once when running the
+//! program several times on it, e.g., to try out different alignment parameters.
+//!
+//! The cache holds the segment names and lengths and the paths of the graph, along with a
+//! fingerprint of the GFA that it was made from and the filter that its paths were chosen with, so
+//! that a cache of an old version of the GFA or of a different set of paths isn't used by mistake.
+//! Integers are stored as LEB128 varints (signed ones zigzag-encoded first), and the steps of each
+//! path are stored just as they're compressed in memory (see `CompressedSteps`), so they don't
+//! have to be decoded to write them or re-encoded to read them.
+//!
+//! Reading all of a GFA that is tens of gigabytes long to checksum it would take nearly as long as
+//! parsing it, so by default the fingerprint is only the size and modification time of the GFA
+//! and a checksum of its first and last blocks. A checksum of the whole GFA can be used instead to
+//! catch changes that keep its size and modification time, and is always used on platforms where
+//! modification times aren't available.
+
+use flate2::Crc;
+use log::{info, warn};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::UNIX_EPOCH;
+
+use crate::InversionError;
+use crate::compressed_steps::CompressedSteps;
+use crate::gfa::{self, PathMetadata, SegmentNames};
+use crate::graph::{GraphPath, PangenomeGraph};
+use crate::path_filter::PathFilter;
+use crate::varint::{self, MAX_VARINT_LENGTH, unzigzag, zigzag};
+
+const MAGIC: &[u8; 8] = b"INVFGRPH";
+
+/// Version of the cache format, to be incremented whenever it changes
+const VERSION: u32 = 4;
+
+/// Number of bytes at each end of a GFA that are checksummed for a quick fingerprint
+const FINGERPRINT_BLOCK_SIZE: u64 = 1 << 20;
+
+/// How much of a GFA to checksum to tell whether a cache was made from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumMode {
+    /// only the first and last blocks, along with the size and modification time of the GFA
+    #[default]
+    Quick,
+
+    /// all of the GFA, which is much slower for big GFAs
+    Full,
+}
+
+/// What a cache records about the GFA it was made from, to tell whether it's still up to date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GfaFingerprint {
+    /// whether `checksum` is of the whole GFA rather than only its first and last blocks
+    full: bool,
+
+    /// size of the GFA in bytes
+    size: u64,
+
+    /// time the GFA was last modified, in nanoseconds since the Unix epoch, or 0 for a checksum of
+    /// the whole GFA, which doesn't need it
+    modified: u128,
+
+    /// CRC32 checksum of the GFA as it is on disk, i.e., without decompressing it
+    checksum: u32,
+}
+
+impl GfaFingerprint {
+    /// Make the fingerprint of a GFA, checksumming all of it if `mode` is `ChecksumMode::Full` or
+    /// if its modification time isn't available.
+    pub fn new(gfa_path: &Path, mode: ChecksumMode) -> Result<Self, InversionError> {
+        let make_error = |err: std::io::Error| {
+            InversionError::GfaParse(format!(
+                "Couldn't open GFA at {}: {}",
+                gfa_path.display(),
+                err
+            ))
+        };
+        let metadata = fs::metadata(gfa_path).map_err(make_error)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+        match (mode, modified) {
+            (ChecksumMode::Quick, Some(modified)) => Ok(Self {
+                full: false,
+                size: metadata.len(),
+                modified: modified.as_nanos(),
+                checksum: ends_checksum(gfa_path, metadata.len())?,
+            }),
+            _ => Ok(Self {
+                full: true,
+                size: metadata.len(),
+                modified: 0,
+                checksum: gfa_checksum(gfa_path)?,
+            }),
+        }
+    }
+
+    fn write(&self, writer: &mut CacheWriter<impl Write>) -> io::Result<()> {
+        writer.write_bytes(&[u8::from(self.full)])?;
+        writer.write_varint(self.size)?;
+        writer.write_varint((self.modified >> 64) as u64)?;
+        writer.write_varint(self.modified as u64)?;
+        writer.write_bytes(&self.checksum.to_le_bytes())
+    }
+
+    fn read(reader: &mut CacheReader<impl Read>) -> Result<Self, InversionError> {
+        Ok(Self {
+            full: reader.read_bytes(1)?[0] != 0,
+            size: reader.read_varint()?,
+            modified: (u128::from(reader.read_varint()?) << 64) | u128::from(reader.read_varint()?),
+            checksum: reader.read_u32()?,
+        })
+    }
+}
+
+/// Read a GFA, using a cache of it if there is an up-to-date one and otherwise writing one.
+///
+/// # Arguments
+/// * `gfa_path` - path to GFA to parse, which may be uncompressed, gzipped, or BGZF-compressed
+/// * `cache_path` - path to the cache, which is read if it was made from the same GFA with the same
+///   filter and is (re)written otherwise
+/// * `filter` - filter for which paths to keep
+/// * `checksum_mode` - how much of the GFA to checksum to tell whether the cache is up to date
+pub fn read_gfa_cached(
+    gfa_path: PathBuf,
+    cache_path: &Path,
+    filter: &PathFilter,
+    checksum_mode: ChecksumMode,
+) -> Result<PangenomeGraph, InversionError> {
+    let fingerprint = GfaFingerprint::new(&gfa_path, checksum_mode)?;
+    let filter_description = filter.to_string();
+    if cache_path.exists() {
+        match read_graph_cache(cache_path, &fingerprint, &filter_description) {
+            Ok(graph) => {
+                info!("Read graph from cache {}", cache_path.display());
+                return Ok(graph);
+            }
+            Err(err) => warn!("{}; reparsing GFA", err),
+        }
+    }
+
+    let graph = gfa::read_gfa_filtered(gfa_path, filter)?;
+    info!("Writing graph cache to {}", cache_path.display());
+    write_graph_cache(&graph, &fingerprint, &filter_description, cache_path)?;
+    Ok(graph)
+}
+
+/// Calculate the CRC32 checksum of a file, as it is on disk, i.e., without decompressing it.
+pub fn gfa_checksum(gfa_path: &Path) -> Result<u32, InversionError> {
+    let file = File::open(gfa_path).map_err(|err| {
+        InversionError::GfaParse(format!(
+            "Couldn't open GFA at {}: {}",
+            gfa_path.display(),
+            err
+        ))
+    })?;
+    let mut reader = BufReader::with_capacity(1 << 20, file);
+    let mut buffer = vec![0; 1 << 20];
+    let mut crc = Crc::new();
+    loop {
+        let num_bytes = reader
+            .read(&mut buffer)
+            .map_err(|err| InversionError::GfaParse(format!("Reading error: {}", err)))?;
+        if num_bytes == 0 {
+            return Ok(crc.sum());
+        }
+        crc.update(&buffer[..num_bytes]);
+    }
+}
+
+/// Calculate the CRC32 checksum of the first and last `FINGERPRINT_BLOCK_SIZE` bytes of a file of
+/// the given size, or of all of it if it's smaller than two blocks.
+fn ends_checksum(gfa_path: &Path, size: u64) -> Result<u32, InversionError> {
+    let read_error = |err: std::io::Error| {
+        InversionError::GfaParse(format!(
+            "Couldn't read GFA at {}: {}",
+            gfa_path.display(),
+            err
+        ))
+    };
+    let mut file = File::open(gfa_path).map_err(read_error)?;
+    let mut crc = Crc::new();
+    let mut buffer = Vec::with_capacity(FINGERPRINT_BLOCK_SIZE as usize);
+    (&mut file)
+        .take(FINGERPRINT_BLOCK_SIZE)
+        .read_to_end(&mut buffer)
+        .map_err(read_error)?;
+    crc.update(&buffer);
+
+    let last_block_start = size
+        .saturating_sub(FINGERPRINT_BLOCK_SIZE)
+        .max(FINGERPRINT_BLOCK_SIZE);
+    buffer.clear();
+    file.seek(SeekFrom::Start(last_block_start))
+        .map_err(read_error)?;
+    file.read_to_end(&mut buffer).map_err(read_error)?;
+    crc.update(&buffer);
+    Ok(crc.sum())
+}
+
+/// Write a graph to a cache file.
+///
+/// The cache is streamed to a temporary file named after the cache and the ID of this process, so
+/// that runs writing the same cache at once don't overwrite each other's files, and then moved
+/// into place, so that an interrupted run doesn't leave a truncated cache behind. The temporary
+/// file is removed if writing it fails.
+///
+/// # Arguments
+/// * `graph` - the graph to cache
+/// * `gfa_fingerprint` - fingerprint of the GFA that the graph was read from
+/// * `filter_description` - the filter that the paths were chosen with, as written by its
+///   `Display` implementation
+/// * `cache_path` - where to write the cache
+pub fn write_graph_cache(
+    graph: &PangenomeGraph,
+    gfa_fingerprint: &GfaFingerprint,
+    filter_description: &str,
+    cache_path: &Path,
+) -> Result<(), InversionError> {
+    let mut temp_path = cache_path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", process::id()));
+    let result = File::create(&temp_path)
+        .and_then(|file| {
+            let mut writer = CacheWriter {
+                out: BufWriter::new(file),
+                crc: Crc::new(),
+            };
+            writer.write_graph(graph, gfa_fingerprint, filter_description)?;
+            let crc = writer.crc.sum();
+            writer.out.write_all(&crc.to_le_bytes())?;
+            writer.out.flush()
+        })
+        .and_then(|()| fs::rename(&temp_path, cache_path));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(InversionError::Cache(format!(
+            "Couldn't write graph cache to {}: {}",
+            cache_path.display(),
+            err
+        )));
+    }
+    Ok(())
+}
+
+/// Read a graph from a cache file.
+///
+/// # Arguments
+/// * `cache_path` - the cache to read
+/// * `gfa_fingerprint` - fingerprint of the GFA that the cache should have been made from
+/// * `filter_description` - the filter that the paths should have been chosen with, as written by
+///   its `Display` implementation
+///
+/// # Returns
+/// * the graph, or an error if the cache can't be read, is corrupt, was written by a different
+///   version of this program, or was made from a different GFA or with a different filter
+pub fn read_graph_cache(
+    cache_path: &Path,
+    gfa_fingerprint: &GfaFingerprint,
+    filter_description: &str,
+) -> Result<PangenomeGraph, InversionError> {
+    let read_error = |err: io::Error| {
+        InversionError::Cache(format!(
+            "Couldn't read graph cache at {}: {}",
+            cache_path.display(),
+            err
+        ))
+    };
+    let make_error = |problem: &str| {
+        InversionError::Cache(format!(
+            "Graph cache at {} {}",
+            cache_path.display(),
+            problem
+        ))
+    };
+    let file = File::open(cache_path).map_err(read_error)?;
+    let size = file.metadata().map_err(read_error)?.len();
+    if size < MAGIC.len() as u64 + 12 {
+        return Err(make_error("is not a graph cache"));
+    }
+
+    // everything but the checksum at the end is checksummed as it's read
+    let mut reader = CacheReader {
+        reader: BufReader::with_capacity(1 << 20, file),
+        crc: Crc::new(),
+        remaining: size - 4,
+    };
+    if reader.read_bytes(MAGIC.len())? != MAGIC {
+        return Err(make_error("is not a graph cache"));
+    }
+    if reader.read_u32()? != VERSION {
+        return Err(make_error(
+            "was written by a different version of inversion_finder",
+        ));
+    }
+    if GfaFingerprint::read(&mut reader)? != *gfa_fingerprint {
+        return Err(make_error("was made from a different GFA"));
+    }
+    if reader.read_string()? != filter_description {
+        return Err(make_error("was made with a different path filter"));
+    }
+
+    let num_segments = reader.read_length()?;
+    let mut segment_names = SegmentNames::new();
+    let mut segment_lengths = Vec::with_capacity(num_segments);
+    for i in 1..=num_segments {
+        if segment_names.intern(&reader.read_string()?)? != i as i64 {
+            return Err(make_error("has duplicate segment names"));
+        }
+        segment_lengths.push(reader.read_signed()?);
+    }
+
+    let num_paths = reader.read_length()?;
+    let mut paths = Vec::with_capacity(num_paths);
+    for _ in 0..num_paths {
+        let name = reader.read_string()?;
+        let start_offset = reader.read_signed()?;
+        let overlaps = (0..reader.read_length()?)
+            .map(|_| reader.read_signed())
+            .collect::<Result<Vec<i64>, InversionError>>()?;
+        // a path can have many more steps than there are bytes left, since runs of consecutive
+        // segments take up so little space, so its number of steps is only checked against its data
+        let num_steps = usize::try_from(reader.read_varint()?)
+            .map_err(|_| make_error("has a path that is too long"))?;
+        let data_length = reader.read_length()?;
+        let steps = CompressedSteps::from_encoded(num_steps, reader.read_bytes(data_length)?)
+            .ok_or_else(|| make_error("has a path with invalid steps"))?;
+        paths.push(GraphPath::new(
+            &name,
+            steps,
+            PathMetadata {
+                start_offset,
+                overlaps,
+            },
+        ));
+    }
+    if reader.remaining != 0 {
+        return Err(make_error("has unexpected data at the end"));
+    }
+    let mut stored_crc = [0; 4];
+    reader
+        .reader
+        .read_exact(&mut stored_crc)
+        .map_err(read_error)?;
+    if reader.crc.sum().to_le_bytes() != stored_crc {
+        return Err(make_error("is corrupt"));
+    }
+
+    PangenomeGraph::new(segment_names, segment_lengths, paths)
+}
+
+/// Writer for the contents of a cache, which keeps a checksum of everything written so far
+struct CacheWriter<W: Write> {
+    out: W,
+    crc: Crc,
+}
+
+impl<W: Write> CacheWriter<W> {
+    /// Write everything but the checksum at the end.
+    fn write_graph(
+        &mut self,
+        graph: &PangenomeGraph,
+        gfa_fingerprint: &GfaFingerprint,
+        filter_description: &str,
+    ) -> io::Result<()> {
+        self.write_bytes(MAGIC)?;
+        self.write_bytes(&VERSION.to_le_bytes())?;
+        gfa_fingerprint.write(self)?;
+        self.write_string(filter_description)?;
+
+        let segment_names = graph.segment_names();
+        self.write_varint(graph.num_segments() as u64)?;
+        for id in 1..=graph.num_segments() as i64 {
+            self.write_string(segment_names.name(id).unwrap())?;
+            self.write_signed(graph.segment_length(id).map_err(io::Error::other)?)?;
+        }
+
+        self.write_varint(graph.paths().len() as u64)?;
+        for path in graph.paths() {
+            self.write_string(path.name())?;
+            self.write_signed(path.metadata().start_offset)?;
+            self.write_varint(path.metadata().overlaps.len() as u64)?;
+            for overlap in &path.metadata().overlaps {
+                self.write_signed(*overlap)?;
+            }
+            self.write_varint(path.steps().len() as u64)?;
+            let data = path.steps().encoded_data();
+            self.write_varint(data.len() as u64)?;
+            self.write_bytes(data)?;
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.crc.update(bytes);
+        self.out.write_all(bytes)
+    }
+
+    fn write_varint(&mut self, value: u64) -> io::Result<()> {
+        let mut buffer = [0; MAX_VARINT_LENGTH];
+        self.write_bytes(varint::encode_varint(value.into(), &mut buffer))
+    }
+
+    fn write_signed(&mut self, value: i64) -> io::Result<()> {
+        self.write_varint(zigzag(value))
+    }
+
+    fn write_string(&mut self, value: &str) -> io::Result<()> {
+        self.write_varint(value.len() as u64)?;
+        self.write_bytes(value.as_bytes())
+    }
+}
+
+/// Reader for the contents of a cache, which keeps a checksum of everything read so far to be
+/// checked once all of it has been read. Until then, the values can be arbitrarily corrupt, so
+/// lengths are checked against the number of bytes left to keep them from being too big.
+struct CacheReader<R: Read> {
+    reader: R,
+    crc: Crc,
+
+    /// number of bytes of the contents that haven't been read yet
+    remaining: u64,
+}
+
+impl<R: Read> Read for CacheReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let max_bytes = self.remaining.min(buffer.len() as u64) as usize;
+        let num_bytes = self.reader.read(&mut buffer[..max_bytes])?;
+        self.crc.update(&buffer[..num_bytes]);
+        self.remaining -= num_bytes as u64;
+        Ok(num_bytes)
+    }
+}
+
+impl<R: Read> CacheReader<R> {
+    fn read_bytes(&mut self, length: usize) -> Result<Vec<u8>, InversionError> {
+        if length as u64 > self.remaining {
+            return Err(truncated_error());
+        }
+        let mut bytes = vec![0; length];
+        self.read_exact(&mut bytes).map_err(contents_read_error)?;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, InversionError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, InversionError> {
+        let value = varint::read_varint(self).map_err(contents_read_error)?;
+        u64::try_from(value).map_err(|_| {
+            InversionError::Cache("Graph cache has an integer that is too big".to_string())
+        })
+    }
+
+    fn read_signed(&mut self) -> Result<i64, InversionError> {
+        Ok(unzigzag(self.read_varint()?))
+    }
+
+    /// Read the length of a list, which can't be more than the number of bytes left because every
+    /// item takes up at least one byte.
+    fn read_length(&mut self) -> Result<usize, InversionError> {
+        let length = self.read_varint()?;
+        if length > self.remaining {
+            return Err(truncated_error());
+        }
+        Ok(length as usize)
+    }
+
+    fn read_string(&mut self) -> Result<String, InversionError> {
+        let length = self.read_length()?;
+        String::from_utf8(self.read_bytes(length)?).map_err(|_| {
+            InversionError::Cache("Graph cache has a name that isn't valid UTF-8".to_string())
+        })
+    }
+}
+
+fn truncated_error() -> InversionError {
+    InversionError::Cache("Graph cache is truncated".to_string())
+}
+
+/// Make an error for a cache that couldn't be read, which is because it's truncated if the end of
+/// its contents was reached.
+fn contents_read_error(err: io::Error) -> InversionError {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => truncated_error(),
+        _ => InversionError::Cache(format!("Couldn't read graph cache: {}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_GFA: &str = concat!(
+        "S\ts1\tACGT\n",
+        "S\ts2\t*\tLN:i:2000\n",
+        "S\ts3\tA\n",
+        "P\tsampleA#0#chr1[100-106]\ts1+,s2-,s3+\t1M,0M\n",
+        "W\tsampleB\t1\tchr1\t10\t16\t<s3<s2>s1\n",
+    );
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}_{}", std::process::id(), name))
+    }
+
+    fn assert_same_graph(graph1: &PangenomeGraph, graph2: &PangenomeGraph) {
+        assert_eq!(graph1.paths(), graph2.paths());
+        assert_eq!(graph1.num_segments(), graph2.num_segments());
+        for id in 1..=graph1.num_segments() as i64 {
+            assert_eq!(
+                graph1.segment_names().name(id),
+                graph2.segment_names().name(id)
+            );
+            assert_eq!(
+                graph1.segment_length(id).unwrap(),
+                graph2.segment_length(id).unwrap()
+            );
+            assert_eq!(
+                graph1.scoring_segment_length(id).unwrap(),
+                graph2.scoring_segment_length(id).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_graph_cache() {
+        let gfa_path = temp_path("test_cache.gfa");
+        let cache_path = temp_path("test_cache.gfa.cache");
+        fs::write(&gfa_path, TEST_GFA).unwrap();
+        let _ = fs::remove_file(&cache_path);
+
+        let no_filter = PathFilter::default();
+        let graph = gfa::read_gfa(gfa_path.clone()).unwrap();
+        let quick = ChecksumMode::Quick;
+        let fingerprint = GfaFingerprint::new(&gfa_path, quick).unwrap();
+        let cached_graph =
+            read_gfa_cached(gfa_path.clone(), &cache_path, &no_filter, quick).unwrap();
+        assert_same_graph(&graph, &cached_graph);
+        assert_same_graph(
+            &graph,
+            &read_graph_cache(&cache_path, &fingerprint, "").unwrap(),
+        );
+
+        // a cache of a different GFA gets replaced, even if it's the same size
+        let different_gfa = GfaFingerprint {
+            size: fingerprint.size + 1,
+            ..fingerprint
+        };
+        assert!(read_graph_cache(&cache_path, &different_gfa, "").is_err());
+        fs::write(&gfa_path, TEST_GFA.replace("LN:i:2000", "LN:i:3000")).unwrap();
+        let new_fingerprint = GfaFingerprint::new(&gfa_path, quick).unwrap();
+        assert_eq!(new_fingerprint.size, fingerprint.size);
+        let cached_graph =
+            read_gfa_cached(gfa_path.clone(), &cache_path, &no_filter, quick).unwrap();
+        assert_eq!(cached_graph.segment_length(2).unwrap(), 3000);
+        assert_eq!(
+            read_graph_cache(&cache_path, &new_fingerprint, "")
+                .unwrap()
+                .segment_length(2)
+                .unwrap(),
+            3000
+        );
+
+        // as does a corrupt one
+        let mut bytes = fs::read(&cache_path).unwrap();
+        bytes[20] ^= 0xff;
+        fs::write(&cache_path, &bytes).unwrap();
+        assert!(read_graph_cache(&cache_path, &new_fingerprint, "").is_err());
+        let cached_graph =
+            read_gfa_cached(gfa_path.clone(), &cache_path, &no_filter, quick).unwrap();
+        assert_eq!(cached_graph.segment_length(2).unwrap(), 3000);
+        assert!(read_graph_cache(&cache_path, &new_fingerprint, "").is_ok());
+
+        // and so does one made with a different filter
+        let filter = PathFilter::parse(&["sampleB"], &[]).unwrap();
+        let cached_graph = read_gfa_cached(gfa_path.clone(), &cache_path, &filter, quick).unwrap();
+        assert_eq!(cached_graph.paths().len(), 1);
+        assert!(read_graph_cache(&cache_path, &new_fingerprint, "").is_err());
+        let cached_graph = read_gfa_cached(gfa_path.clone(), &cache_path, &filter, quick).unwrap();
+        assert_eq!(cached_graph.paths()[0].name(), "sampleB#1#chr1");
+
+        // and so does one checked with a different checksum mode
+        let full_fingerprint = GfaFingerprint::new(&gfa_path, ChecksumMode::Full).unwrap();
+        assert_eq!(full_fingerprint.checksum, gfa_checksum(&gfa_path).unwrap());
+        assert!(read_graph_cache(&cache_path, &new_fingerprint, &filter.to_string()).is_ok());
+        assert!(read_graph_cache(&cache_path, &full_fingerprint, &filter.to_string()).is_err());
+        read_gfa_cached(gfa_path.clone(), &cache_path, &filter, ChecksumMode::Full).unwrap();
+        assert!(read_graph_cache(&cache_path, &full_fingerprint, &filter.to_string()).is_ok());
+
+        fs::remove_file(&gfa_path).unwrap();
+        fs::remove_file(&cache_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_graph_cache_error() {
+        let gfa_path = temp_path("test_cache_error.gfa");
+        fs::write(&gfa_path, TEST_GFA).unwrap();
+        let graph = gfa::read_gfa(gfa_path.clone()).unwrap();
+        let fingerprint = GfaFingerprint::new(&gfa_path, ChecksumMode::Quick).unwrap();
+
+        // the cache can't be moved into place over a directory, and the temporary file is removed
+        let cache_path = temp_path("test_cache_error_dir");
+        fs::create_dir_all(&cache_path).unwrap();
+        assert!(write_graph_cache(&graph, &fingerprint, "", &cache_path).is_err());
+        let mut temp_cache_path = cache_path.clone().into_os_string();
+        temp_cache_path.push(format!(".{}.tmp", process::id()));
+        assert!(!Path::new(&temp_cache_path).exists());
+
+        fs::remove_dir(&cache_path).unwrap();
+        fs::remove_file(&gfa_path).unwrap();
+    }
+
+    #[test]
+    fn test_quick_fingerprint() {
+        let path = temp_path("test_fingerprint.gfa");
+        let block_size = FINGERPRINT_BLOCK_SIZE as usize;
+        let mut contents = vec![b'A'; 3 * block_size];
+        fs::write(&path, &contents).unwrap();
+        let fingerprint = GfaFingerprint::new(&path, ChecksumMode::Quick).unwrap();
+        let full_fingerprint = GfaFingerprint::new(&path, ChecksumMode::Full).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let rewrite = |contents: &[u8]| {
+            // keep the modification time, as if the file had been changed without touching it
+            fs::write(&path, contents).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+
+        // only the first and last blocks are checksummed quickly
+        contents[block_size + 10] = b'C';
+        rewrite(&contents);
+        assert_eq!(
+            GfaFingerprint::new(&path, ChecksumMode::Quick).unwrap(),
+            fingerprint
+        );
+        assert_ne!(
+            GfaFingerprint::new(&path, ChecksumMode::Full).unwrap(),
+            full_fingerprint
+        );
+        for changed_byte in [0, 3 * block_size - 1] {
+            let mut changed = contents.clone();
+            changed[changed_byte] = b'G';
+            rewrite(&changed);
+            assert_ne!(
+                GfaFingerprint::new(&path, ChecksumMode::Quick).unwrap(),
+                fingerprint
+            );
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_varints() {
+        let values = [0, 1, -1, 63, -64, 64, 1 << 40, i64::MAX, i64::MIN];
+        let mut writer = CacheWriter {
+            out: Vec::new(),
+            crc: Crc::new(),
+        };
+        for value in values {
+            writer.write_signed(value).unwrap();
+        }
+        let bytes = writer.out;
+        assert_eq!(bytes[..3], [0, 2, 1]);
+        let mut reader = CacheReader {
+            reader: bytes.as_slice(),
+            crc: Crc::new(),
+            remaining: bytes.len() as u64,
+        };
+        for value in values {
+            assert_eq!(reader.read_signed().unwrap(), value);
+        }
+        assert!(reader.read_signed().is_err());
+        assert_eq!(reader.crc.sum(), writer.crc.sum());
+    }
+}
@@ -0,0 +1,513 @@
+//! Finding inversions by chaining anchors, for inversions too long to align with dynamic
+//! programming.
+//!
+//! An anchor is a segment that is in each path exactly once, in opposite orientations, so that it
+//! pins down a single cell of the dynamic programming matrix of path1 and the reverse complement
+//! of path2. An inversion shows up as a series of anchors that are in the same order in both, so
+//! the inversions are found by chaining the anchors: each anchor scores the match score of its
+//! segment, and each pair of consecutive anchors in a chain costs `gap_cost` for every bp between
+//! them in either path. The best chain ending at every anchor is found in O(n log n) time for n
+//! anchors, using a Fenwick tree of the best chains ending before each anchor in path2.
+//!
+//! The ends of a chain are only the first and last anchors of the inversion, so segments that are
+//! in one of the paths more than once aren't included even if they're part of it. To find exactly
+//! where an inversion starts and ends, the steps of the paths around each end of the chain can be
+//! aligned with dynamic programming.
+
+use crate::InversionError;
+
+use crate::align::{self, Alignment, AlignmentAlgorithm, AlignmentOptions};
+use crate::compressed_steps::CompressedSteps;
+use crate::graph::PangenomeGraph;
+use log::debug;
+use std::collections::HashMap;
+
+/// A segment that is in both paths exactly once, in opposite orientations
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    /// index of the segment in path1
+    i: usize,
+
+    /// index of the segment in the reverse complement of path2
+    j: usize,
+
+    /// match score of the segment
+    score: i64,
+
+    /// position of the start and end of the segment in path1, in bp
+    path1_interval: (i64, i64),
+
+    /// position of the start and end of the segment in the reverse complement of path2, in bp
+    path2_interval: (i64, i64),
+}
+
+/// Where a segment is in a path
+#[derive(Debug, Clone, Copy)]
+struct Occurrence {
+    index: usize,
+    interval: (i64, i64),
+}
+
+/// Find where every segment that is in a path exactly once, in either orientation, is in it, with
+/// `None` for the segments that are in it more than once.
+fn unique_segments(
+    path: &CompressedSteps,
+    graph: &PangenomeGraph,
+) -> Result<HashMap<i64, Option<Occurrence>>, InversionError> {
+    let mut segments = HashMap::new();
+    let mut position = 0;
+    for (index, segment) in path.iter().enumerate() {
+        let length = graph.scoring_segment_length(segment)?;
+        segments
+            .entry(segment.abs())
+            .and_modify(|occurrence| *occurrence = None)
+            .or_insert(Some(Occurrence {
+                index,
+                interval: (position, position + length),
+            }));
+        position += length;
+    }
+    Ok(segments)
+}
+
+/// Find the anchors of path1 and the reverse complement of path2, in order of their index in path1.
+fn find_anchors(
+    path1: &CompressedSteps,
+    path2_rev: &CompressedSteps,
+    graph: &PangenomeGraph,
+    alignment_options: &AlignmentOptions,
+) -> Result<Vec<Anchor>, InversionError> {
+    let path2_segments = unique_segments(path2_rev, graph)?;
+    let mut anchors = Vec::new();
+    for (segment, occurrence1) in unique_segments(path1, graph)? {
+        let (Some(occurrence1), Some(Some(occurrence2))) =
+            (occurrence1, path2_segments.get(&segment))
+        else {
+            continue;
+        };
+        // a segment in the same orientation in both paths isn't inverted
+        if path1.get(occurrence1.index) != path2_rev.get(occurrence2.index) {
+            continue;
+        }
+        let length = occurrence1.interval.1 - occurrence1.interval.0;
+        anchors.push(Anchor {
+            i: occurrence1.index,
+            j: occurrence2.index,
+            score: alignment_options.scoring_scheme.match_score(length),
+            path1_interval: occurrence1.interval,
+            path2_interval: occurrence2.interval,
+        });
+    }
+    anchors.sort_by_key(|anchor| anchor.i);
+    Ok(anchors)
+}
+
+/// A Fenwick tree for finding the maximum of a prefix of a list of values, which can only increase
+struct MaxFenwickTree {
+    tree: Vec<Option<(f64, usize)>>,
+}
+
+impl MaxFenwickTree {
+    fn new(len: usize) -> Self {
+        Self {
+            tree: vec![None; len + 1],
+        }
+    }
+
+    /// Raise the value at `index` to `value` if it's higher, along with the index of what it's
+    /// the value of
+    fn update(&mut self, index: usize, value: (f64, usize)) {
+        let mut k = index + 1;
+        while k < self.tree.len() {
+            if self.tree[k].is_none_or(|(max, _)| value.0 > max) {
+                self.tree[k] = Some(value);
+            }
+            k += k & k.wrapping_neg();
+        }
+    }
+
+    /// Find the maximum of the values before `index`
+    fn prefix_max(&self, index: usize) -> Option<(f64, usize)> {
+        let mut max: Option<(f64, usize)> = None;
+        let mut k = index;
+        while k > 0 {
+            if let Some(value) = self.tree[k]
+                && max.is_none_or(|(max, _)| value.0 > max)
+            {
+                max = Some(value);
+            }
+            k -= k & k.wrapping_neg();
+        }
+        max
+    }
+}
+
+/// Find the score of the best chain ending at each anchor, and the anchor before it in the chain,
+/// if any.
+fn chain_anchors(anchors: &[Anchor], gap_cost: f64) -> Vec<(f64, Option<usize>)> {
+    // rank of each anchor by its index in path2
+    let mut order: Vec<usize> = (0..anchors.len()).collect();
+    order.sort_by_key(|&a| anchors[a].j);
+    let mut ranks = vec![0; anchors.len()];
+    for (rank, a) in order.into_iter().enumerate() {
+        ranks[a] = rank;
+    }
+
+    // the cost of the gap between anchors b and a is separable into a part for each of them, so
+    // the tree holds the score of each chain plus the part for the anchor it ends at
+    let mut tree = MaxFenwickTree::new(anchors.len());
+    let mut chains = Vec::with_capacity(anchors.len());
+    for (a, anchor) in anchors.iter().enumerate() {
+        let start = gap_cost * (anchor.path1_interval.0 + anchor.path2_interval.0) as f64;
+        let end = gap_cost * (anchor.path1_interval.1 + anchor.path2_interval.1) as f64;
+        let chain = match tree.prefix_max(ranks[a]) {
+            Some((value, b)) if value - start > 0.0 => {
+                (anchor.score as f64 + value - start, Some(b))
+            }
+            _ => (anchor.score as f64, None),
+        };
+        tree.update(ranks[a], (chain.0 + end, a));
+        chains.push(chain);
+    }
+    chains
+}
+
+/// Split the anchors into chains, starting from the best chain ending at any anchor and then
+/// taking the best chain of the anchors that haven't been used yet each time, returning the indices
+/// of each chain's anchors in order.
+fn extract_chains(chains: &[(f64, Option<usize>)]) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..chains.len()).collect();
+    order.sort_by(|&a, &b| chains[b].0.total_cmp(&chains[a].0));
+    let mut used = vec![false; chains.len()];
+    let mut extracted = Vec::new();
+    for end in order {
+        if used[end] {
+            continue;
+        }
+        let mut chain = vec![end];
+        used[end] = true;
+        while let Some(previous) = chains[*chain.last().unwrap()].1
+            && !used[previous]
+        {
+            chain.push(previous);
+            used[previous] = true;
+        }
+        chain.reverse();
+        extracted.push(chain);
+    }
+    extracted
+}
+
+/// Score a chain of anchors from scratch, since it may have been cut short by another chain
+fn score_chain(anchors: &[Anchor], chain: &[usize], gap_cost: f64) -> i64 {
+    let matches: i64 = chain.iter().map(|&a| anchors[a].score).sum();
+    let gaps: i64 = chain
+        .windows(2)
+        .map(|pair| {
+            let (b, a) = (&anchors[pair[0]], &anchors[pair[1]]);
+            (a.path1_interval.0 - b.path1_interval.1) + (a.path2_interval.0 - b.path2_interval.1)
+        })
+        .sum();
+    matches - (gap_cost * gaps as f64).round() as i64
+}
+
+/// The cells of the dynamic programming matrix where a chain starts and ends, and its score
+#[derive(Debug, Clone, Copy)]
+struct ChainedRegion {
+    start: (usize, usize),
+    end: (usize, usize),
+    score: i64,
+}
+
+impl ChainedRegion {
+    /// Whether the two regions share any steps of either path
+    fn overlaps(&self, other: &ChainedRegion) -> bool {
+        (self.start.0 <= other.end.0 && other.start.0 <= self.end.0)
+            || (self.start.1 <= other.end.1 && other.start.1 <= self.end.1)
+    }
+}
+
+/// Extend an alignment from `(i, j)` over up to `window` steps before it in both paths (or after
+/// it, if `forward`) with dynamic programming, returning the cell where the extended alignment
+/// starts (or ends) and its score, including the segment at `(i, j)`.
+fn refine_end(
+    path1: &CompressedSteps,
+    path2_rev: &CompressedSteps,
+    (i, j): (usize, usize),
+    window: usize,
+    forward: bool,
+    graph: &PangenomeGraph,
+    alignment_options: &AlignmentOptions,
+) -> Result<((usize, usize), i64), InversionError> {
+    let window_range = |path: &CompressedSteps, index: usize| {
+        if forward {
+            index..(index + window + 1).min(path.len())
+        } else {
+            index.saturating_sub(window)..index + 1
+        }
+    };
+    let range1 = window_range(path1, i);
+    let range2 = window_range(path2_rev, j);
+    let mut window1 = path1.decode(range1.clone());
+    let mut window2 = path2_rev.decode(range2.clone());
+    // the alignment has to end at (i, j), so to extend it forward, align the windows backward
+    if forward {
+        window1.reverse();
+        window2.reverse();
+    }
+    let alignment = align::align_paths_subproblem_to_end(
+        &window1,
+        &window2,
+        graph,
+        alignment_options.scoring_scheme,
+        alignment_options.gap_penalties,
+    )?;
+    let cell = if forward {
+        (
+            range1.end - 1 - alignment.path1_start_index,
+            range2.end - 1 - alignment.path2_start_index,
+        )
+    } else {
+        (
+            range1.start + alignment.path1_start_index,
+            range2.start + alignment.path2_start_index,
+        )
+    };
+    Ok((cell, alignment.score))
+}
+
+/// Find the inversions of path2 relative to path1 by chaining the anchors of path1 and the reverse
+/// complement of path2.
+///
+/// Every chain of anchors that doesn't overlap a better one in either path is reported, from the
+/// start of path1 to the end, as long as it scores at least `alignment_options.min_alignment_score`
+/// if that's given. If `alignment_options.refine_window` is given, the ends of each chain are
+/// extended with dynamic programming first.
+///
+/// # Arguments
+///
+/// * `path1` and `path2_rev`: paths to align, with path2 reverse-complemented
+/// * `graph`: graph containing the paths' segments
+/// * `gap_cost`: penalty for each bp between consecutive anchors of a chain in either path
+/// * `alignment_options`: parameters for scoring the anchors and refining the ends of the chains
+///
+/// # Returns
+///
+/// * an alignment for each inversion found, with indices into `path1` and path2 and
+///   `alignment_path2` in the orientation of path2, as returned by `align::align_paths`
+pub(crate) fn align_paths_chained(
+    path1: &CompressedSteps,
+    path2_rev: &CompressedSteps,
+    graph: &PangenomeGraph,
+    gap_cost: f64,
+    alignment_options: AlignmentOptions,
+) -> Result<Vec<Alignment>, InversionError> {
+    let anchors = find_anchors(path1, path2_rev, graph, &alignment_options)?;
+    let chains = chain_anchors(&anchors, gap_cost);
+    let extracted = extract_chains(&chains);
+    debug!(
+        "Chained {} anchors into {} chains",
+        anchors.len(),
+        extracted.len()
+    );
+
+    let mut regions = Vec::with_capacity(extracted.len());
+    for chain in extracted {
+        let first = &anchors[chain[0]];
+        let last = &anchors[*chain.last().unwrap()];
+        let mut region = ChainedRegion {
+            start: (first.i, first.j),
+            end: (last.i, last.j),
+            score: score_chain(&anchors, &chain, gap_cost),
+        };
+        if let Some(window) = alignment_options.refine_window {
+            let (start, start_score) = refine_end(
+                path1,
+                path2_rev,
+                region.start,
+                window,
+                false,
+                graph,
+                &alignment_options,
+            )?;
+            let (end, end_score) = refine_end(
+                path1,
+                path2_rev,
+                region.end,
+                window,
+                true,
+                graph,
+                &alignment_options,
+            )?;
+            // the scores of the extensions include the first and last anchors
+            region = ChainedRegion {
+                start,
+                end,
+                score: region.score + start_score - first.score + end_score - last.score,
+            };
+        }
+        regions.push(region);
+    }
+
+    // take the best chains first so that they win any overlaps, as in `align_subproblem_local`
+    regions.sort_by_key(|region| (std::cmp::Reverse(region.score), region.start));
+    let mut accepted: Vec<ChainedRegion> = Vec::new();
+    for region in regions {
+        let too_low = alignment_options
+            .min_alignment_score
+            .is_some_and(|min_score| region.score < min_score);
+        if !too_low && !accepted.iter().any(|other| region.overlaps(other)) {
+            accepted.push(region);
+        }
+    }
+    accepted.sort_by_key(|region| region.start);
+
+    let alignments = accepted
+        .into_iter()
+        .map(|ChainedRegion { start, end, score }| {
+            let mut alignment_path1 = path1.decode(start.0..end.0 + 1);
+            let mut alignment_path2 = path2_rev.decode(start.1..end.1 + 1);
+            alignment_path1.dedup();
+            alignment_path2.dedup();
+            let alignment = Alignment {
+                alignment_path1,
+                alignment_path2,
+                path1_start_index: start.0,
+                path1_end_index: end.0,
+                path2_start_index: start.1,
+                path2_end_index: end.1,
+                score,
+                subproblem_size: (path1.len(), path2_rev.len()),
+                algorithm: AlignmentAlgorithm::Chain,
+            };
+            align::unreverse_alignment(alignment, 0, 0, path2_rev.len())
+        })
+        .collect();
+    Ok(alignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::AlignmentStrategy;
+    use crate::scoring::{GapPenalties, SegmentLength};
+
+    fn chain_options(
+        min_alignment_score: Option<i64>,
+        refine_window: Option<usize>,
+    ) -> AlignmentOptions<'static> {
+        AlignmentOptions {
+            max_highmem_path_length: 100,
+            max_lowmem_drop: 1000,
+            exact_lowmem: false,
+            max_path_length: 100,
+            min_alignment_score,
+            scoring_scheme: &SegmentLength,
+            gap_penalties: GapPenalties::default(),
+            strategy: AlignmentStrategy::Chaining { gap_cost: 0.5 },
+            refine_window,
+        }
+    }
+
+    #[test]
+    fn test_max_fenwick_tree() {
+        let mut tree = MaxFenwickTree::new(5);
+        assert_eq!(tree.prefix_max(5), None);
+        tree.update(3, (2.0, 0));
+        tree.update(1, (1.0, 1));
+        tree.update(4, (5.0, 2));
+        assert_eq!(tree.prefix_max(1), None);
+        assert_eq!(tree.prefix_max(2), Some((1.0, 1)));
+        assert_eq!(tree.prefix_max(4), Some((2.0, 0)));
+        assert_eq!(tree.prefix_max(5), Some((5.0, 2)));
+    }
+
+    #[test]
+    fn test_align_paths_chained() {
+        // an inversion of 2000 segments, with a few segments in it that aren't shared and one
+        // that's in the reference twice, and a short inversion after it
+        let mut path1: Vec<i64> = (1..=2010).collect();
+        let mut inverted: Vec<i64> = (11..=2010).rev().map(|x| -x).collect();
+        inverted[500] = 3000;
+        inverted[1000] = 3001;
+        let mut path2: Vec<i64> = (1..=10).collect();
+        path2.extend(inverted);
+        path1.extend([2011, 1000, 2012, 2013, 2014, 2015]);
+        path2.extend([2011, 3002, 2012, -2014, -2013, 2015]);
+        let graph = PangenomeGraph::from_segment_lengths(vec![100; 3002], Vec::new()).unwrap();
+        let path1 = CompressedSteps::from(path1);
+        let path2 = CompressedSteps::from(path2);
+
+        // the big inversion is too long to be aligned in one piece with dynamic programming
+        let dp_options = AlignmentOptions {
+            strategy: AlignmentStrategy::DynamicProgramming,
+            ..chain_options(None, None)
+        };
+        let alignments = align::align_paths(&path1, &path2, &graph, dp_options).unwrap();
+        assert!(
+            alignments
+                .iter()
+                .all(|alignment| alignment.path1_start_index > 10)
+        );
+
+        let alignments =
+            align::align_paths(&path1, &path2, &graph, chain_options(None, None)).unwrap();
+        assert_eq!(alignments.len(), 2);
+        assert_eq!(alignments[0].algorithm, AlignmentAlgorithm::Chain);
+        assert_eq!(
+            (
+                alignments[0].path1_start_index,
+                alignments[0].path1_end_index
+            ),
+            (10, 2009)
+        );
+        assert_eq!(
+            (
+                alignments[0].path2_start_index,
+                alignments[0].path2_end_index
+            ),
+            (10, 2009)
+        );
+        // 1997 anchors, with three gaps where a segment of each path is skipped, at 0.5 per bp
+        assert_eq!(alignments[0].score, 1997 * 100 - 3 * 100);
+        assert_eq!(alignments[0].alignment_path1.len(), 2000);
+        assert_eq!(alignments[0].alignment_path2[0], -2010);
+        assert_eq!(alignments[1].alignment_path1, vec![2013, 2014]);
+        assert_eq!(alignments[1].alignment_path2, vec![-2014, -2013]);
+        assert_eq!(alignments[1].score, 200);
+
+        // chains scoring less than the minimum aren't reported
+        let alignments =
+            align::align_paths(&path1, &path2, &graph, chain_options(Some(1000), None)).unwrap();
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(alignments[0].path1_start_index, 10);
+    }
+
+    #[test]
+    fn test_refine_chain_ends() {
+        // segment 2 is in both paths twice, so it isn't an anchor, but it's part of the inversion
+        let path1 = CompressedSteps::from(vec![1, 2, 3, 4, 5, 6, 7, 2]);
+        let path2 = CompressedSteps::from(vec![1, -6, -5, -4, -3, -2, 7, 2]);
+        let graph = PangenomeGraph::from_segment_lengths(vec![100; 7], Vec::new()).unwrap();
+
+        let alignments =
+            align::align_paths(&path1, &path2, &graph, chain_options(None, None)).unwrap();
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(alignments[0].alignment_path1, vec![3, 4, 5, 6]);
+        assert_eq!(alignments[0].score, 400);
+
+        let alignments =
+            align::align_paths(&path1, &path2, &graph, chain_options(None, Some(3))).unwrap();
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(alignments[0].alignment_path1, vec![2, 3, 4, 5, 6]);
+        assert_eq!(alignments[0].alignment_path2, vec![-6, -5, -4, -3, -2]);
+        assert_eq!(
+            (
+                alignments[0].path2_start_index,
+                alignments[0].path2_end_index
+            ),
+            (1, 5)
+        );
+        assert_eq!(alignments[0].score, 500);
+    }
+}
@@ -0,0 +1,379 @@
+use std::ops::Range;
+
+use crate::varint::{self, MAX_VARINT_LENGTH, unzigzag, zigzag};
+
+/// Number of steps in each independently decodable block
+const BLOCK_LENGTH: usize = 256;
+
+/// The steps of a path, compressed to take up less memory.
+///
+/// In most graphs, consecutive steps of a path are usually consecutive segments, so the steps are
+/// stored as the differences between consecutive segment IDs, with runs of the same difference
+/// (usually +1 or -1) run-length encoded. This usually takes one or two bytes for a whole run of
+/// steps, rather than eight bytes for each step. The steps are split into blocks of
+/// `BLOCK_LENGTH` steps, each starting with an absolute segment ID, so that any step can be found
+/// by decoding only the block it's in.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::compressed_steps::CompressedSteps;
+///
+/// let steps: Vec<i64> = (1..=1000).chain([-2000, -1999, 7]).collect();
+/// let compressed = CompressedSteps::from(steps.as_slice());
+/// assert_eq!(compressed.len(), 1003);
+/// assert_eq!(compressed.get(600), Some(601));
+/// assert_eq!(compressed.get(1001), Some(-1999));
+/// assert_eq!(compressed.get(1003), None);
+/// assert_eq!(compressed.decode(998..1002), vec![999, 1000, -2000, -1999]);
+/// assert_eq!(compressed.to_vec(), steps);
+/// assert!(compressed.encoded_size() < 100);
+/// assert_eq!(compressed.reverse_complement().get(0), Some(-7));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompressedSteps {
+    len: usize,
+
+    /// the encoded blocks one after another
+    data: Vec<u8>,
+
+    /// offset in `data` of the start of each block
+    block_offsets: Vec<usize>,
+}
+
+impl CompressedSteps {
+    /// Number of steps
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Size in bytes of the compressed steps, not counting the small fixed-size part
+    pub fn encoded_size(&self) -> usize {
+        self.data.len() + self.block_offsets.len() * size_of::<usize>()
+    }
+
+    /// Get a step by its index, or `None` if there is no such step.
+    pub fn get(&self, index: usize) -> Option<i64> {
+        self.iter_from(index).next()
+    }
+
+    /// Iterate over the steps in order.
+    pub fn iter(&self) -> StepIter<'_> {
+        self.iter_from(0)
+    }
+
+    /// Iterate over the steps in order starting from the one at `start`, only decoding the block
+    /// that it's in rather than all of the steps before it.
+    pub fn iter_from(&self, start: usize) -> StepIter<'_> {
+        let mut iter = StepIter {
+            steps: self,
+            index: start - start % BLOCK_LENGTH,
+            position: 0,
+            step: 0,
+            run_difference: 0,
+            run_remaining: 0,
+        };
+        for _ in 0..start % BLOCK_LENGTH {
+            iter.next();
+        }
+        iter
+    }
+
+    /// Decode a range of steps.
+    pub fn decode(&self, range: Range<usize>) -> Vec<i64> {
+        let end = range.end.min(self.len);
+        if range.start >= end {
+            return Vec::new();
+        }
+        self.iter_from(range.start)
+            .take(end - range.start)
+            .collect()
+    }
+
+    /// Decode all of the steps.
+    pub fn to_vec(&self) -> Vec<i64> {
+        self.iter().collect()
+    }
+
+    /// The encoded blocks one after another, which can be turned back into compressed steps with
+    /// `from_encoded`
+    pub(crate) fn encoded_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Make compressed steps from the number of steps and their encoded blocks as returned by
+    /// `encoded_data`, or return `None` if the data isn't valid for that number of steps.
+    pub(crate) fn from_encoded(len: usize, data: Vec<u8>) -> Option<Self> {
+        // the blocks are only checked here, so that decoding them later can't fail
+        let is_u64 = |value: u128| u64::try_from(value).is_ok();
+        let mut block_offsets = Vec::new();
+        let mut position = 0;
+        let mut index = 0;
+        while index < len {
+            block_offsets.push(position);
+            if !is_u64(try_read_varint(&data, &mut position)?) {
+                return None;
+            }
+            let block_end = index.saturating_add(BLOCK_LENGTH).min(len);
+            index += 1;
+            while index < block_end {
+                let tagged_difference = try_read_varint(&data, &mut position)?;
+                let run_remaining = if tagged_difference & 1 == 1 {
+                    try_read_varint(&data, &mut position)?
+                } else {
+                    0
+                };
+                if !is_u64(tagged_difference >> 1) || !is_u64(run_remaining) {
+                    return None;
+                }
+                index = index.checked_add(usize::try_from(run_remaining).ok()?.checked_add(1)?)?;
+            }
+            if index > block_end {
+                return None;
+            }
+        }
+        if position != data.len() {
+            return None;
+        }
+        block_offsets.shrink_to_fit();
+        Some(Self {
+            len,
+            data,
+            block_offsets,
+        })
+    }
+
+    /// Make the steps of the reverse complement of the path, i.e., the steps in reverse order with
+    /// their orientations flipped.
+    pub fn reverse_complement(&self) -> Self {
+        // decode one block at a time so that the whole path never has to be decoded at once
+        let mut encoder = Encoder::default();
+        for block_start in (0..self.len).step_by(BLOCK_LENGTH).rev() {
+            let block = self.decode(block_start..block_start + BLOCK_LENGTH);
+            for step in block.iter().rev() {
+                encoder.push(step.wrapping_neg());
+            }
+        }
+        encoder.finish()
+    }
+}
+
+impl FromIterator<i64> for CompressedSteps {
+    fn from_iter<T: IntoIterator<Item = i64>>(iter: T) -> Self {
+        let mut encoder = Encoder::default();
+        for step in iter {
+            encoder.push(step);
+        }
+        encoder.finish()
+    }
+}
+
+impl From<&[i64]> for CompressedSteps {
+    fn from(steps: &[i64]) -> Self {
+        steps.iter().copied().collect()
+    }
+}
+
+impl From<Vec<i64>> for CompressedSteps {
+    fn from(steps: Vec<i64>) -> Self {
+        Self::from(steps.as_slice())
+    }
+}
+
+impl<'a> IntoIterator for &'a CompressedSteps {
+    type Item = i64;
+    type IntoIter = StepIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over compressed steps
+#[derive(Debug, Clone)]
+pub struct StepIter<'a> {
+    steps: &'a CompressedSteps,
+
+    /// index of the next step
+    index: usize,
+
+    /// position in the encoded data of the next thing to decode
+    position: usize,
+
+    /// the last step decoded
+    step: i64,
+
+    /// difference between consecutive steps in the current run
+    run_difference: i64,
+
+    /// number of steps left in the current run
+    run_remaining: u64,
+}
+
+impl Iterator for StepIter<'_> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        if self.index >= self.steps.len {
+            return None;
+        }
+        if self.index.is_multiple_of(BLOCK_LENGTH) {
+            self.position = self.steps.block_offsets[self.index / BLOCK_LENGTH];
+            self.step = unzigzag(read_varint(&self.steps.data, &mut self.position) as u64);
+            self.run_remaining = 0;
+        } else if self.run_remaining > 0 {
+            self.step = self.step.wrapping_add(self.run_difference);
+            self.run_remaining -= 1;
+        } else {
+            // differences are tagged with whether they start a run
+            let tagged_difference = read_varint(&self.steps.data, &mut self.position);
+            self.run_difference = unzigzag((tagged_difference >> 1) as u64);
+            if tagged_difference & 1 == 1 {
+                self.run_remaining = read_varint(&self.steps.data, &mut self.position) as u64;
+            }
+            self.step = self.step.wrapping_add(self.run_difference);
+        }
+        self.index += 1;
+        Some(self.step)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.steps.len.saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for StepIter<'_> {}
+
+/// Encoder that compresses steps one at a time
+#[derive(Default)]
+struct Encoder {
+    steps: CompressedSteps,
+
+    /// the last step added
+    previous_step: i64,
+
+    /// the difference between steps in the current run and the number of steps in it after the
+    /// first, if there is a run that hasn't been written yet
+    run: Option<(i64, u64)>,
+}
+
+impl Encoder {
+    fn push(&mut self, step: i64) {
+        if self.steps.len.is_multiple_of(BLOCK_LENGTH) {
+            self.finish_run();
+            self.steps.block_offsets.push(self.steps.data.len());
+            self.write_varint(zigzag(step) as u128);
+        } else {
+            let difference = step.wrapping_sub(self.previous_step);
+            match &mut self.run {
+                Some((run_difference, run_remaining)) if *run_difference == difference => {
+                    *run_remaining += 1;
+                }
+                _ => {
+                    self.finish_run();
+                    self.run = Some((difference, 0));
+                }
+            }
+        }
+        self.previous_step = step;
+        self.steps.len += 1;
+    }
+
+    fn finish_run(&mut self) {
+        if let Some((difference, run_remaining)) = self.run.take() {
+            let tagged_difference = (zigzag(difference) as u128) << 1 | (run_remaining > 0) as u128;
+            self.write_varint(tagged_difference);
+            if run_remaining > 0 {
+                self.write_varint(run_remaining as u128);
+            }
+        }
+    }
+
+    fn write_varint(&mut self, value: u128) {
+        let mut buffer = [0; MAX_VARINT_LENGTH];
+        self.steps
+            .data
+            .extend_from_slice(varint::encode_varint(value, &mut buffer));
+    }
+
+    fn finish(mut self) -> CompressedSteps {
+        self.finish_run();
+        self.steps.data.shrink_to_fit();
+        self.steps.block_offsets.shrink_to_fit();
+        self.steps
+    }
+}
+
+/// Read a varint from the encoded data of compressed steps, which is always valid because it was
+/// either encoded by `Encoder` or checked by `CompressedSteps::from_encoded`.
+fn read_varint(data: &[u8], position: &mut usize) -> u128 {
+    try_read_varint(data, position).expect("compressed steps are valid")
+}
+
+fn try_read_varint(data: &[u8], position: &mut usize) -> Option<u128> {
+    let mut rest = data.get(*position..)?;
+    let value = varint::read_varint(&mut rest).ok()?;
+    *position = data.len() - rest.len();
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_steps() {
+        let mut steps: Vec<i64> = (1..=2000).collect();
+        steps.extend((500..=1500).rev().map(|x| -x));
+        steps.extend([i64::MAX, i64::MIN, 0, 1, 1, 1, 5]);
+        steps.extend((0..1000).map(|i| (i * 7919) % 1013 - 500));
+        let compressed = CompressedSteps::from(steps.as_slice());
+        assert_eq!(compressed.len(), steps.len());
+        assert_eq!(compressed.to_vec(), steps);
+        for start in [0, 1, 255, 256, 257, 2000, 3001, 3003, steps.len() - 1] {
+            assert_eq!(compressed.get(start), Some(steps[start]));
+            assert_eq!(
+                compressed.iter_from(start).collect::<Vec<i64>>(),
+                &steps[start..]
+            );
+            assert_eq!(
+                compressed.decode(start..start + 300),
+                &steps[start..(start + 300).min(steps.len())]
+            );
+        }
+        assert_eq!(compressed.get(steps.len()), None);
+        assert_eq!(compressed.iter_from(steps.len() + 5).next(), None);
+
+        let reversed: Vec<i64> = steps.iter().rev().map(|x| x.wrapping_neg()).collect();
+        assert_eq!(compressed.reverse_complement().to_vec(), reversed);
+        assert_eq!(
+            compressed.reverse_complement(),
+            CompressedSteps::from(reversed)
+        );
+
+        // runs of consecutive segments take up much less space than the steps themselves
+        let compressed = CompressedSteps::from((1..=100000).collect::<Vec<i64>>());
+        assert!(compressed.encoded_size() < 100000 * size_of::<i64>() / 100);
+        assert!(CompressedSteps::from(Vec::new()).is_empty());
+
+        // the encoded data can be turned back into the same steps, but only with the right length
+        let compressed = CompressedSteps::from(steps.as_slice());
+        let data = compressed.encoded_data().to_vec();
+        assert_eq!(
+            CompressedSteps::from_encoded(steps.len(), data.clone()),
+            Some(compressed)
+        );
+        for len in [0, steps.len() - 1, steps.len() + 1] {
+            assert_eq!(CompressedSteps::from_encoded(len, data.clone()), None);
+        }
+        assert_eq!(
+            CompressedSteps::from_encoded(steps.len(), data[..data.len() - 1].to_vec()),
+            None
+        );
+    }
+}
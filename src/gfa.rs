@@ -1,179 +1,1281 @@
+use flate2::read::MultiGzDecoder;
+use log::info;
+use memmap2::Mmap;
+use noodles_bgzf as bgzf;
+use rayon::prelude::*;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, Read};
+use std::num::NonZero;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::thread;
 
 use crate::InversionError;
+use crate::compressed_steps::CompressedSteps;
+use crate::graph::{GraphPath, PangenomeGraph};
+use crate::path_filter::PathFilter;
+
+/// Information about a path other than the segments it traverses
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PathMetadata {
+    /// 0-based position on its sequence at which the path starts
+    pub start_offset: i64,
+
+    /// overlap in bp between each pair of consecutive steps of the path, or empty if the steps of
+    /// the path do not overlap
+    pub overlaps: Vec<i64>,
+}
+
+/// Table for interning segment names into a dense space of integer segment IDs.
+///
+/// Orientation is encoded elsewhere by the sign of a segment ID, so IDs start at 1 rather than 0.
+/// Segment names are arbitrary strings, so IDs are assigned in the order names are first seen and
+/// will generally not be the same as the names even if the names are integers.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::gfa::SegmentNames;
+///
+/// let mut segment_names = SegmentNames::new();
+/// assert_eq!(segment_names.intern("s7").unwrap(), 1);
+/// assert_eq!(segment_names.intern("s3").unwrap(), 2);
+/// assert_eq!(segment_names.intern("s7").unwrap(), 1);
+/// assert_eq!(segment_names.name(-2), Some("s3"));
+/// assert_eq!(segment_names.format_oriented(-2), "s3-");
+/// ```
+#[derive(Debug, Default)]
+pub struct SegmentNames {
+    /// map of name to ID, which is only filled in once there are names that aren't integers
+    ids: HashMap<String, i64>,
+    names: Vec<String>,
+
+    /// ID of each segment indexed by its name, as long as every name is an integer, which is the
+    /// case for most graphs and is much faster to look up than a string; 0 means no such segment
+    numeric_ids: Vec<i64>,
+
+    /// whether any of the names aren't integers, so that `ids` has to be used instead
+    has_other_names: bool,
+}
+
+impl SegmentNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the ID of a segment name, assigning it a new ID if it hasn't been seen before.
+    pub fn intern(&mut self, name: &str) -> Result<i64, InversionError> {
+        if let Some(id) = self.id(name) {
+            return Ok(id);
+        }
+        let id = i64::try_from(self.names.len() + 1)
+            .map_err(|_| InversionError::Overflow("too many segments".to_string()))?;
+        self.names.push(name.to_string());
+
+        if !self.has_other_names {
+            // don't let a few huge integers make the table of numeric IDs take up lots of memory
+            match parse_numeric_name(name) {
+                Some(number) if number < 4 * self.names.len() + (1 << 20) => {
+                    if number >= self.numeric_ids.len() {
+                        self.numeric_ids.resize(number + 1, 0);
+                    }
+                    self.numeric_ids[number] = id;
+                    return Ok(id);
+                }
+                _ => {
+                    self.has_other_names = true;
+                    self.numeric_ids = Vec::new();
+                    self.ids = (1..)
+                        .zip(&self.names)
+                        .map(|(id, name)| (name.clone(), id))
+                        .collect();
+                }
+            }
+        }
+        self.ids.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    /// Get the ID of a segment name, if it has been interned.
+    pub fn id(&self, name: &str) -> Option<i64> {
+        if self.has_other_names {
+            self.ids.get(name).copied()
+        } else {
+            let id = *self.numeric_ids.get(parse_numeric_name(name)?)?;
+            (id != 0).then_some(id)
+        }
+    }
+
+    /// Get the original name of a segment, ignoring the orientation encoded in the sign of `id`.
+    pub fn name(&self, id: i64) -> Option<&str> {
+        let index: usize = id.unsigned_abs().try_into().ok()?;
+        self.names
+            .get(index.checked_sub(1)?)
+            .map(|name| name.as_str())
+    }
+
+    /// Format an oriented segment ID as its original name followed by "+" or "-".
+    pub fn format_oriented(&self, id: i64) -> String {
+        format!(
+            "{}{}",
+            self.name(id).unwrap_or("?"),
+            if id < 0 { "-" } else { "+" }
+        )
+    }
+
+    /// Number of distinct segment names interned
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Parse a segment name that is an integer written the usual way, i.e., with no sign or leading
+/// zeros, so that two names are the same if and only if they parse to the same number.
+fn parse_numeric_name(name: &str) -> Option<usize> {
+    let bytes = name.as_bytes();
+    if bytes.is_empty()
+        || bytes.len() > 18
+        || (bytes[0] == b'0' && bytes.len() > 1)
+        || !bytes.iter().all(u8::is_ascii_digit)
+    {
+        return None;
+    }
+    name.parse().ok()
+}
 
 /// Parse the path part of a GFA P-line.
 ///
 /// # Arguments
 ///
 /// * `path_string` - the path field from a P-line of a GFA
+/// * `segment_names` - table to intern segment names into
 ///
 /// # Returns
 ///
 /// The path as a vector of segment IDs, where the orientation is encoded by the sign of the
-/// segment ID. So, for example, "1-" is encoded as -1, while "1+" is encoded as 1.
+/// segment ID. So, for example, if segment "1" is interned as ID 1, "1-" is encoded as -1, while
+/// "1+" is encoded as 1.
 ///
 /// # Examples
 ///
 /// ```
 /// use inversion_finder::gfa;
 ///
-/// assert_eq!(gfa::parse_gfa_path("1-,2+,3-,235+").unwrap(), vec![-1, 2, -3, 235]);
+/// let mut segment_names = gfa::SegmentNames::new();
+/// assert_eq!(
+///     gfa::parse_gfa_path("1-,2+,3-,235+", &mut segment_names).unwrap(),
+///     vec![-1, 2, -3, 4]
+/// );
+/// assert_eq!(segment_names.name(4), Some("235"));
 /// ```
-pub fn parse_gfa_path(path_string: &str) -> Result<Vec<i32>, InversionError> {
-    let re = Regex::new(r"(\d+)([+-])").unwrap();
-    let mut path_list: Vec<i32> = Vec::new();
-    for segment in path_string.split(",") {
-        let caps = re.captures(segment).ok_or(make_segment_error(segment))?;
-        path_list.push(
-            match caps.get(2).ok_or(make_segment_error(segment))?.as_str() {
-                "+" => caps
-                    .get(1)
-                    .map(|segment_id| segment_id.as_str().parse::<i32>())
-                    .ok_or(make_segment_error(segment))?
-                    .map_err(|_| make_segment_error(segment))?,
-                "-" => {
-                    -1 * caps
-                        .get(1)
-                        .map(|segment_id| segment_id.as_str().parse::<i32>())
-                        .ok_or(make_segment_error(segment))?
-                        .map_err(|_| make_segment_error(segment))?
+pub fn parse_gfa_path(
+    path_string: &str,
+    segment_names: &mut SegmentNames,
+) -> Result<Vec<i64>, InversionError> {
+    let mut path_list: Vec<i64> = Vec::new();
+    for_each_step(path_string, StepFormat::Path, |name, is_reverse| {
+        let segment_id = segment_names.intern(name)?;
+        path_list.push(if is_reverse { -segment_id } else { segment_id });
+        Ok::<(), InversionError>(())
+    })?;
+    Ok(path_list)
+}
+
+fn make_segment_error(segment: &str) -> InversionError {
+    InversionError::GfaParse(format!("Invalid segment format '{}'", segment))
+}
+
+/// Parse the walk part of a GFA W-line.
+///
+/// # Arguments
+///
+/// * `walk_string` - the walk field from a W-line of a GFA
+/// * `segment_names` - table to intern segment names into
+///
+/// # Returns
+///
+/// The walk as a vector of segment IDs, with orientation encoded by the sign of the segment ID in
+/// the same way as `parse_gfa_path`. So, for example, ">1" is encoded as 1, while "<1" is encoded
+/// as -1.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::gfa;
+///
+/// let mut segment_names = gfa::SegmentNames::new();
+/// assert_eq!(
+///     gfa::parse_gfa_walk(">s12<s13>s12", &mut segment_names).unwrap(),
+///     vec![1, -2, 1]
+/// );
+/// ```
+pub fn parse_gfa_walk(
+    walk_string: &str,
+    segment_names: &mut SegmentNames,
+) -> Result<Vec<i64>, InversionError> {
+    let mut walk_list: Vec<i64> = Vec::new();
+    for_each_step(walk_string, StepFormat::Walk, |name, is_reverse| {
+        let segment_id = segment_names.intern(name)?;
+        walk_list.push(if is_reverse { -segment_id } else { segment_id });
+        Ok::<(), InversionError>(())
+    })?;
+    Ok(walk_list)
+}
+
+/// The way that the steps of a path are written in a GFA
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StepFormat {
+    /// comma-separated segment names each followed by an orientation, e.g., `1+,2-`, as in P-lines
+    Path,
+
+    /// segment names each preceded by an orientation, e.g., `>1<2`, as in W-lines
+    Walk,
+}
+
+/// Split the steps of a path into segment names and orientations, calling `f(name, is_reverse)`
+/// on each one in order.
+///
+/// This works on the bytes of the steps rather than using a regex because it's run on every step
+/// of every path in the graph, of which there can be billions.
+fn for_each_step<E: From<InversionError>>(
+    steps: &str,
+    format: StepFormat,
+    mut f: impl FnMut(&str, bool) -> Result<(), E>,
+) -> Result<(), E> {
+    match format {
+        StepFormat::Path => {
+            for segment in steps.split(',') {
+                match segment.as_bytes() {
+                    [_, .., orientation @ (b'+' | b'-')] => {
+                        f(&segment[..segment.len() - 1], *orientation == b'-')?
+                    }
+                    _ => return Err(make_segment_error(segment).into()),
+                }
+            }
+        }
+        StepFormat::Walk => {
+            let bytes = steps.as_bytes();
+            let is_orientation = |byte: u8| byte == b'>' || byte == b'<';
+            // the end of the previous step, so that we can make sure that there is nothing between
+            // steps that doesn't look like a step
+            let mut previous_end = 0;
+            // the next step starts at the next orientation followed by a name
+            while let Some(start) = (previous_end..bytes.len().saturating_sub(1))
+                .find(|&i| is_orientation(bytes[i]) && !is_orientation(bytes[i + 1]))
+            {
+                let end = bytes[start + 1..]
+                    .iter()
+                    .position(|byte| is_orientation(*byte))
+                    .map_or(bytes.len(), |length| start + 1 + length);
+                if start != previous_end {
+                    return Err(make_segment_error(&steps[previous_end..end]).into());
                 }
-                _ => return Err(make_segment_error(segment)),
+                f(&steps[start + 1..end], bytes[start] == b'<')?;
+                previous_end = end;
+            }
+            if previous_end == 0 || previous_end != bytes.len() {
+                return Err(make_segment_error(&steps[previous_end..]).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The steps of a path parsed using the segment IDs assigned so far
+struct ParsedSteps {
+    /// segment IDs with orientation indicated by sign, or 0 for segments that don't have IDs yet
+    steps: Vec<i64>,
+
+    /// whether any segments didn't have IDs yet
+    has_unknown_segments: bool,
+}
+
+/// Parse the steps of a path without assigning new segment IDs, so that several paths (or parts
+/// of a path) can be parsed at once.
+fn parse_steps(
+    steps: &str,
+    format: StepFormat,
+    segment_names: &SegmentNames,
+) -> Result<ParsedSteps, InversionError> {
+    let mut parsed = ParsedSteps {
+        steps: Vec::new(),
+        has_unknown_segments: false,
+    };
+    for_each_step(steps, format, |name, is_reverse| {
+        let segment_id = segment_names.id(name).unwrap_or_else(|| {
+            parsed.has_unknown_segments = true;
+            0
+        });
+        parsed
+            .steps
+            .push(if is_reverse { -segment_id } else { segment_id });
+        Ok::<(), InversionError>(())
+    })?;
+    Ok(parsed)
+}
+
+/// Parse the steps of a path like `parse_steps`, but split long paths into pieces to parse in
+/// parallel, because a single path can be a whole chromosome.
+fn parse_steps_parallel(
+    steps: &str,
+    format: StepFormat,
+    segment_names: &SegmentNames,
+) -> Result<ParsedSteps, InversionError> {
+    const PIECE_LENGTH: usize = 1 << 20;
+    if steps.len() <= PIECE_LENGTH {
+        return parse_steps(steps, format, segment_names);
+    }
+
+    // split between steps, i.e., at commas in paths and in front of orientations followed by
+    // names in walks
+    let bytes = steps.as_bytes();
+    let mut pieces = Vec::new();
+    let mut piece_start = 0;
+    while piece_start < bytes.len() {
+        let search_start = (piece_start + PIECE_LENGTH).min(bytes.len());
+        let (piece_end, next_piece_start) = match format {
+            StepFormat::Path => match bytes[search_start..].iter().position(|b| *b == b',') {
+                Some(offset) => (search_start + offset, search_start + offset + 1),
+                None => (bytes.len(), bytes.len()),
             },
-        )
+            StepFormat::Walk => match bytes[search_start..].windows(2).position(
+                |pair| matches!(pair, [b'>' | b'<', next] if *next != b'>' && *next != b'<'),
+            ) {
+                Some(offset) => (search_start + offset, search_start + offset),
+                None => (bytes.len(), bytes.len()),
+            },
+        };
+        pieces.push(&steps[piece_start..piece_end]);
+        piece_start = next_piece_start;
+        // a path ending in a comma has an empty last step, which is an error
+        if piece_start == bytes.len() && piece_end < bytes.len() {
+            pieces.push("");
+        }
     }
-    return Ok(path_list);
+
+    let parsed_pieces: Vec<Result<ParsedSteps, InversionError>> = pieces
+        .into_par_iter()
+        .map(|piece| parse_steps(piece, format, segment_names))
+        .collect();
+    if parsed_pieces.iter().any(|piece| piece.is_err()) {
+        // parse the whole thing again to get the same error message as if it hadn't been split
+        return parse_steps(steps, format, segment_names);
+    }
+    let mut parsed = ParsedSteps {
+        steps: Vec::with_capacity(parsed_pieces.iter().flatten().map(|p| p.steps.len()).sum()),
+        has_unknown_segments: false,
+    };
+    for piece in parsed_pieces.into_iter().flatten() {
+        parsed.steps.extend(piece.steps);
+        parsed.has_unknown_segments |= piece.has_unknown_segments;
+    }
+    Ok(parsed)
 }
 
-fn make_segment_error(segment: &str) -> InversionError {
-    InversionError::GfaParse(format!("Invalid segment format '{}'", segment))
+/// Get the length of a segment from the fields of its S-line.
+///
+/// The length is taken from the `LN` tag if there is one, and otherwise from the sequence. If
+/// both are present, they have to agree.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::gfa;
+///
+/// assert_eq!(gfa::parse_segment_length(&["S", "1", "ACGT"]).unwrap(), 4);
+/// assert_eq!(gfa::parse_segment_length(&["S", "1", "*", "LN:i:1000"]).unwrap(), 1000);
+/// assert_eq!(gfa::parse_segment_length(&["S", "1", "ACGT", "LN:i:4"]).unwrap(), 4);
+/// assert!(gfa::parse_segment_length(&["S", "1", "ACGT", "LN:i:5"]).is_err());
+/// assert!(gfa::parse_segment_length(&["S", "1", "*"]).is_err());
+/// ```
+pub fn parse_segment_length(fields: &[&str]) -> Result<i64, InversionError> {
+    let mut tag_length: Option<i64> = None;
+    for tag in fields.iter().skip(3) {
+        if let Some(length) = tag.strip_prefix("LN:i:") {
+            tag_length = Some(length.parse().map_err(|_| {
+                InversionError::GfaParse(format!(
+                    "Invalid LN tag '{}' for segment '{}'",
+                    tag, fields[1]
+                ))
+            })?);
+        }
+    }
+
+    let sequence_length: Option<i64> =
+        if fields[2] == "*" {
+            None
+        } else {
+            Some(i64::try_from(fields[2].len()).map_err(|_| {
+                InversionError::Overflow(format!("length of segment '{}'", fields[1]))
+            })?)
+        };
+
+    match (sequence_length, tag_length) {
+        (Some(sequence_length), Some(tag_length)) if sequence_length != tag_length => {
+            Err(InversionError::GfaParse(format!(
+                "Segment '{}' has a sequence of length {} but an LN tag of {}",
+                fields[1], sequence_length, tag_length
+            )))
+        }
+        (_, Some(length)) | (Some(length), None) => Ok(length),
+        (None, None) => Err(InversionError::GfaParse(format!(
+            "Segment '{}' has neither a sequence nor an LN tag, so its length is unknown",
+            fields[1]
+        ))),
+    }
 }
 
-/// Read a GFA into memory.
+/// Parse the overlaps part of a GFA P-line.
 ///
-/// Only keep the information in the GFA that we will need later: the length of each segment, and
-/// the paths.
+/// Each overlap is a CIGAR string, and the length of the overlap is the number of bases of the
+/// second segment that it consumes. Overlaps given as "*" are assumed to be 0.
 ///
 /// # Arguments
-/// * `path` - path to GFA to parse
+///
+/// * `overlaps_string` - the overlaps field from a P-line of a GFA
+/// * `num_steps` - number of steps in the path
 ///
 /// # Returns
-/// * `segment_lengths` - map of segment ID to segment length in bp
-/// * `paths` - map of path ID to vector of path with segment orientation indicated by sign
-/// * `path_names` - the keys of `paths`, but in the order they were read, because I find the
-///   nondeterministic order that stuff comes out of the HashMap to be disturbing
-pub fn read_gfa(
-    path: PathBuf,
-) -> Result<(HashMap<i32, i32>, HashMap<String, Vec<i32>>, Vec<String>), InversionError> {
-    let file = File::open(&path).map_err(|err| {
-        InversionError::GfaParse(format!("Couldn't open GFA at {}: {}", path.display(), err))
-    })?;
-    let reader = BufReader::new(file);
-
-    let mut segment_lengths: HashMap<i32, i32> = HashMap::new();
-    let mut paths = HashMap::new();
-    let mut path_names = Vec::new();
-
-    for (i, line_result) in reader.lines().enumerate() {
-        let line = line_result
-            .map_err(|err| InversionError::GfaParse(format!("Reading error: {}", err)))?;
-        let fields: Vec<&str> = line.split("\t").collect();
-
-        match fields[0] {
-            "S" => {
-                segment_lengths.insert(
-                    fields[1].to_string().parse().map_err(|_| {
-                        InversionError::GfaParse(format!(
-                            concat!(
-                                "Segment IDs must be integers, ",
-                                "but ID '{}' on line {} is not an integer."
-                            ),
-                            fields[1],
-                            i + 1
-                        ))
-                    })?,
-                    fields[2]
-                        .len()
-                        .try_into()
-                        .expect("Segment longer than max i64???"),
-                );
-            }
-            "P" => {
-                paths.insert(
-                    fields[1].to_string(),
-                    parse_gfa_path(fields[2]).map_err(|err| {
-                        InversionError::GfaParse(format!("{} (line {})", err, i + 1))
-                    })?,
-                );
-                path_names.push(fields[1].to_string());
-            }
-            _ => {}
-        }
-    }
-
-    return Ok((segment_lengths, paths, path_names));
-}
-
-/// Lookup start and end positions of segments in a path.
+///
+/// The length of the overlap between each pair of consecutive steps, or an empty vector if the
+/// overlaps field is "*" or all of the overlaps are 0.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::gfa;
+///
+/// assert_eq!(gfa::parse_gfa_overlaps("31M,2M1I29M", 3).unwrap(), vec![31, 32]);
+/// assert_eq!(gfa::parse_gfa_overlaps("0M,0M", 3).unwrap(), Vec::<i64>::new());
+/// assert_eq!(gfa::parse_gfa_overlaps("*", 3).unwrap(), Vec::<i64>::new());
+/// assert!(gfa::parse_gfa_overlaps("31M", 3).is_err());
+/// ```
+pub fn parse_gfa_overlaps(
+    overlaps_string: &str,
+    num_steps: usize,
+) -> Result<Vec<i64>, InversionError> {
+    if overlaps_string == "*" {
+        return Ok(Vec::new());
+    }
+
+    let mut overlaps: Vec<i64> = Vec::new();
+    for cigar in overlaps_string.split(",") {
+        let overlap = if cigar == "*" {
+            0
+        } else {
+            cigar_overlap(cigar).ok_or_else(|| make_overlap_error(cigar))?
+        };
+        overlaps.push(overlap);
+    }
+
+    // a circular path can have an overlap between its last and first steps, which we don't need
+    if overlaps.len() == num_steps {
+        overlaps.pop();
+    }
+    if overlaps.len() + 1 != num_steps {
+        return Err(InversionError::GfaParse(format!(
+            "Path has {} steps but {} overlaps",
+            num_steps,
+            overlaps.len()
+        )));
+    }
+
+    if overlaps.iter().all(|overlap| *overlap == 0) {
+        overlaps.clear();
+    }
+    Ok(overlaps)
+}
+
+fn make_overlap_error(cigar: &str) -> InversionError {
+    InversionError::GfaParse(format!("Invalid overlap CIGAR '{}'", cigar))
+}
+
+/// Add up the lengths of the operations of a CIGAR string that consume bases of the second segment,
+/// going through it a byte at a time, or return `None` if it isn't a valid CIGAR string.
+fn cigar_overlap(cigar: &str) -> Option<i64> {
+    let mut overlap = 0i64;
+    // length of the operation being read, if any of its digits have been read yet
+    let mut length: Option<i64> = None;
+    for byte in cigar.bytes() {
+        match byte {
+            b'0'..=b'9' => {
+                let digit = i64::from(byte - b'0');
+                length = Some(length.unwrap_or(0).checked_mul(10)?.checked_add(digit)?);
+            }
+            b'M' | b'I' | b'=' | b'X' => overlap = overlap.checked_add(length.take()?)?,
+            b'D' | b'N' | b'S' | b'H' | b'P' => {
+                length.take()?;
+            }
+            _ => return None,
+        }
+    }
+    (!cigar.is_empty() && length.is_none()).then_some(overlap)
+}
+
+/// Split a subpath range suffix off of a path name.
+///
+/// Tools that extract subgraphs name the paths in them with the range of the original path they
+/// cover, either as `name:start-end` (e.g., PGGB and odgi) or as `name[start-end]` (e.g., vg).
+/// In both cases, coordinates are 0-based and the end is exclusive.
 ///
 /// # Arguments
 ///
-/// * `path`: list of segments in path
-/// * `segment_lengths`: map of segment ID to segment length in bp
-/// * `segments`: indices in `path` of segments to look up
+/// * `path_name`: name of a path, which may or may not have a range suffix
 ///
 /// # Returns
 ///
-/// * map of segment index to tuple of start and end positions of segment in path, in bp
+/// * the name without the range suffix
+/// * the start and end of the range, if there is one. The end is `None` if the suffix only gives
+///   a start position, e.g., `name[1000]`.
 ///
 /// # Examples
 ///
 /// ```
 /// use inversion_finder::gfa;
-/// use std::collections::HashMap;
-///
-/// let mut segment_lengths: HashMap<i32, i32> = HashMap::new();
-/// for i in 0..8 {
-///     segment_lengths.insert(i, 100);
-/// }
-/// let base_positions =
-///     gfa::lookup_base_positions(&[1,-2,3,4,5,-6,-7], &segment_lengths, &[1,3,6]).unwrap();
-/// assert_eq!(base_positions.get(&1).unwrap(), &(101, 200));
-/// assert_eq!(base_positions.get(&6).unwrap(), &(601, 700));
+///
+/// assert_eq!(
+///     gfa::parse_subpath_name("sampleA#0#chr1:1000000-2000000"),
+///     ("sampleA#0#chr1", Some((1000000, Some(2000000))))
+/// );
+/// assert_eq!(
+///     gfa::parse_subpath_name("chr1[1000000-2000000]"),
+///     ("chr1", Some((1000000, Some(2000000))))
+/// );
+/// assert_eq!(gfa::parse_subpath_name("chr1[1000]"), ("chr1", Some((1000, None))));
+/// assert_eq!(gfa::parse_subpath_name("sampleA#0#chr1"), ("sampleA#0#chr1", None));
+/// assert_eq!(gfa::parse_subpath_name("HLA:A-B"), ("HLA:A-B", None));
 /// ```
-pub fn lookup_base_positions(
-    path: &[i32],
-    segment_lengths: &HashMap<i32, i32>,
-    segment_indices: &[i32],
-) -> Result<HashMap<i32, (i32, i32)>, InversionError> {
-    let segment_indices_set = HashSet::<i32>::from_iter(segment_indices.iter().cloned());
-    let mut segment_positions: HashMap<i32, (i32, i32)> = HashMap::new();
-
-    let mut current_position = 0;
-    for (i, segment) in path.iter().map(|s| s.abs()).enumerate() {
-        let this_segment_length = segment_lengths
-            .get(&segment)
-            .ok_or(InversionError::SegmentNotFound(segment))?;
-        if segment_indices_set.contains(&i.try_into().expect("i32 overflow")) {
-            segment_positions.insert(
-                i.try_into().expect("i32 overflow"),
-                (current_position + 1, current_position + this_segment_length),
+pub fn parse_subpath_name(path_name: &str) -> (&str, Option<(i64, Option<i64>)>) {
+    // this gets called a lot on the same few names, so only compile the regex once
+    static RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(.+?)(?::(\d+)-(\d+)|\[(\d+)(?:-(\d+))?\])$").unwrap());
+    let Some(caps) = RE.captures(path_name) else {
+        return (path_name, None);
+    };
+    let start = caps.get(2).or(caps.get(4)).unwrap().as_str().parse();
+    let end = caps
+        .get(3)
+        .or(caps.get(5))
+        .map(|end| end.as_str().parse())
+        .transpose();
+    match (start, end) {
+        (Ok(start), Ok(end)) => (caps.get(1).unwrap().as_str(), Some((start, end))),
+        // too big to be a position, so probably not actually a range
+        _ => (path_name, None),
+    }
+}
+
+/// Make a PanSN-style path name for a GFA W-line, i.e., `sample#haplotype#sequence`.
+pub(crate) fn make_walk_name(sample_id: &str, haplotype_index: &str, sequence_id: &str) -> String {
+    format!("{}#{}#{}", sample_id, haplotype_index, sequence_id)
+}
+
+/// The kind of compression used for a GFA file
+#[derive(Debug, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Bgzf,
+}
+
+/// Figure out how a file is compressed by looking at its first few bytes.
+///
+/// BGZF files are gzip files whose header has an extra subfield with identifier "BC", so any file
+/// starting with the gzip magic bytes that isn't BGZF is treated as regular gzip.
+fn detect_compression(header: &[u8]) -> Compression {
+    if header.len() < 2 || header[0..2] != [0x1f, 0x8b] {
+        Compression::None
+    } else if header.len() >= 16 && header[3] & 0x04 != 0 && header[12..14] == [b'B', b'C'] {
+        Compression::Bgzf
+    } else {
+        Compression::Gzip
+    }
+}
+
+/// Open a GFA for reading, decompressing it on the fly if it is gzip- or BGZF-compressed.
+pub(crate) fn open_gfa(path: &Path) -> Result<Box<dyn BufRead>, InversionError> {
+    let file = File::open(path).map_err(|err| {
+        InversionError::GfaParse(format!("Couldn't open GFA at {}: {}", path.display(), err))
+    })?;
+    let mut reader = BufReader::new(file);
+    let compression = detect_compression(
+        reader
+            .fill_buf()
+            .map_err(|err| InversionError::GfaParse(format!("Reading error: {}", err)))?,
+    );
+
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Bgzf => {
+            let worker_count = thread::available_parallelism().unwrap_or(NonZero::<usize>::MIN);
+            Box::new(bgzf::io::MultithreadedReader::with_worker_count(
+                worker_count,
+                reader,
+            ))
+        }
+    })
+}
+
+/// Read a GFA into memory.
+///
+/// Only keep the information in the GFA that we will need later: the length of each segment, and
+/// the paths. Paths are read both from P-lines and from GFA 1.1 W-lines; walks are named in PanSN
+/// format, i.e., `sample#haplotype#sequence`. Each path's metadata records its 0-based start
+/// position on its sequence (the start field of a W-line, the start of the range in a P-line name
+/// like `chr1:1000-2000`, or 0 otherwise), and the overlaps between its steps.
+///
+/// Uncompressed GFAs are memory-mapped rather than read, and the GFA is parsed in blocks of lines
+/// whose paths are parsed in parallel, but the result is the same as parsing it one line at a
+/// time: segment IDs are assigned in the order the segments are first seen, and if there is
+/// something wrong with the GFA, the error is about the first line with a problem.
+///
+/// # Arguments
+/// * `path` - path to GFA to parse, which may be uncompressed, gzipped, or BGZF-compressed
+///
+/// # Returns
+/// * the graph, with its paths in the order they were read, because I find the nondeterministic
+///   order that stuff comes out of a HashMap to be disturbing
+pub fn read_gfa(path: PathBuf) -> Result<PangenomeGraph, InversionError> {
+    read_gfa_filtered(path, &PathFilter::default())
+}
+
+/// Read a GFA into memory like `read_gfa`, but only keep the paths that pass a filter.
+///
+/// The paths that don't pass the filter are skipped without parsing their steps, so they don't
+/// take up any memory and aren't checked for errors.
+///
+/// # Arguments
+/// * `path` - path to GFA to parse, which may be uncompressed, gzipped, or BGZF-compressed
+/// * `filter` - filter for which paths to keep
+pub fn read_gfa_filtered(
+    path: PathBuf,
+    filter: &PathFilter,
+) -> Result<PangenomeGraph, InversionError> {
+    let make_read_error =
+        |err: io::Error| InversionError::GfaParse(format!("Reading error: {}", err));
+    let mut file = File::open(&path).map_err(|err| {
+        InversionError::GfaParse(format!("Couldn't open GFA at {}: {}", path.display(), err))
+    })?;
+    let mut header = Vec::new();
+    (&mut file)
+        .take(18)
+        .read_to_end(&mut header)
+        .map_err(make_read_error)?;
+
+    let mut parser = GfaParser::default();
+    if detect_compression(&header) == Compression::None {
+        // an empty file can't be mapped, but it also doesn't have anything in it to parse
+        if !header.is_empty() {
+            // SAFETY: the file is only read, and we have to trust that nothing else changes it
+            // while it's being read, as we would if reading it normally
+            let contents = unsafe { Mmap::map(&file) }.map_err(make_read_error)?;
+            let mut block_start = 0;
+            while block_start < contents.len() {
+                let block_end = (block_start + GFA_BLOCK_SIZE).min(contents.len());
+                let block_end = contents[block_end..]
+                    .iter()
+                    .position(|byte| *byte == b'\n')
+                    .map_or(contents.len(), |offset| block_end + offset + 1);
+                parser.parse_block(&contents[block_start..block_end], filter)?;
+                block_start = block_end;
+            }
+        }
+    } else {
+        let mut reader = open_gfa(&path)?;
+        let mut block = Vec::new();
+        loop {
+            block.clear();
+            (&mut reader)
+                .take(GFA_BLOCK_SIZE as u64)
+                .read_to_end(&mut block)
+                .map_err(make_read_error)?;
+            if block.is_empty() {
+                break;
+            }
+            if block.last() != Some(&b'\n') {
+                reader
+                    .read_until(b'\n', &mut block)
+                    .map_err(make_read_error)?;
+            }
+            parser.parse_block(&block, filter)?;
+        }
+    }
+    parser.finish()
+}
+
+/// Approximate size in bytes of the blocks of lines that a GFA is parsed in
+const GFA_BLOCK_SIZE: usize = 64 << 20;
+
+/// A line of a GFA that has been split into fields, but whose steps, if it's a path, haven't been
+/// parsed yet
+enum GfaLine<'a> {
+    Segment { name: &'a str, length: i64 },
+    Path(PathLine<'a>),
+    FilteredPath,
+}
+
+/// A P- or W-line of a GFA
+struct PathLine<'a> {
+    line_number: usize,
+    name: String,
+    steps: &'a str,
+    format: StepFormat,
+    overlaps: &'a str,
+    start_offset: i64,
+}
+
+/// A path that has been parsed using the segment IDs assigned so far
+struct ParsedPath {
+    name: String,
+
+    /// the steps, compressed as soon as they're parsed so that the uncompressed steps of every
+    /// path never have to be in memory at once
+    steps: CompressedSteps,
+    metadata: PathMetadata,
+
+    /// number of segments that had been seen when the path was read, for working out the order
+    /// that segments were first seen in
+    num_segments_before: usize,
+
+    /// if some of the segments in the path didn't have IDs yet, the steps, so that they can be
+    /// parsed again once every segment has an ID
+    unparsed_steps: Option<(String, StepFormat)>,
+
+    /// line of the GFA that the path is on, for errors found when its steps are parsed again
+    line_number: usize,
+}
+
+/// State of a GFA that is being parsed a block of lines at a time
+#[derive(Default)]
+struct GfaParser {
+    /// number of lines parsed so far
+    num_lines: usize,
+
+    segment_names: SegmentNames,
+
+    /// length of each segment by ID - 1
+    segment_lengths: Vec<i64>,
+
+    paths: Vec<ParsedPath>,
+    path_names: HashSet<String>,
+
+    /// number of paths that didn't pass the filter
+    num_filtered_paths: usize,
+}
+
+impl GfaParser {
+    /// Parse a block of whole lines of a GFA.
+    fn parse_block(&mut self, block: &[u8], filter: &PathFilter) -> Result<(), InversionError> {
+        let block = block.strip_suffix(b"\n").unwrap_or(block);
+        let lines: Vec<&[u8]> = block.split(|byte| *byte == b'\n').collect();
+        let first_line_number = self.num_lines + 1;
+        self.num_lines += lines.len();
+
+        // split the lines into fields in parallel, and then go through them in order to assign
+        // IDs to segments and check for duplicate path names
+        let gfa_lines: Vec<Result<GfaLine, InversionError>> = lines
+            .into_par_iter()
+            .enumerate()
+            .filter_map(|(i, line)| parse_gfa_line(line, first_line_number + i, filter).transpose())
+            .collect();
+        let mut path_lines = Vec::new();
+        let mut first_error = None;
+        for gfa_line in gfa_lines {
+            match gfa_line {
+                Ok(GfaLine::Segment { name, length }) => {
+                    let segment_id = self.segment_names.intern(name)?;
+                    if segment_id as usize > self.segment_lengths.len() {
+                        self.segment_lengths.push(length);
+                    } else {
+                        self.segment_lengths[segment_id as usize - 1] = length;
+                    }
+                }
+                Ok(GfaLine::Path(path_line)) => {
+                    if !self.path_names.insert(path_line.name.clone()) {
+                        first_error = Some(make_duplicate_path_error(
+                            &path_line.name,
+                            path_line.line_number,
+                        ));
+                        break;
+                    }
+                    path_lines.push((path_line, self.segment_names.len()));
+                }
+                Ok(GfaLine::FilteredPath) => self.num_filtered_paths += 1,
+                Err(err) => {
+                    first_error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        // parse the steps of the paths before the first error, if there was one, in case there's
+        // an error in one of them that comes earlier in the GFA
+        let segment_names = &self.segment_names;
+        let paths: Vec<Result<ParsedPath, InversionError>> = path_lines
+            .into_par_iter()
+            .map(|(path_line, num_segments_before)| {
+                parse_path_line(path_line, num_segments_before, segment_names)
+            })
+            .collect();
+        for path in paths {
+            self.paths.push(path?);
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish parsing the GFA once all of its lines have been parsed.
+    fn finish(mut self) -> Result<PangenomeGraph, InversionError> {
+        if self.num_filtered_paths > 0 {
+            info!(
+                "Skipped {} paths that didn't pass the filter",
+                self.num_filtered_paths
             );
         }
-        current_position += this_segment_length;
+
+        // segment IDs are assigned when S-lines are read, so paths that were read before the
+        // S-lines of some of their segments have to be parsed again
+        for path in &mut self.paths {
+            if let Some((steps, format)) = path.unparsed_steps.take() {
+                let parsed_steps = parse_steps_parallel(&steps, format, &self.segment_names)
+                    .map_err(|err| {
+                        InversionError::GfaParse(format!("{} (line {})", err, path.line_number))
+                    })?;
+                if parsed_steps.has_unknown_segments {
+                    let mut missing_segment = None;
+                    for_each_step(&steps, format, |name, _| {
+                        if missing_segment.is_none() && self.segment_names.id(name).is_none() {
+                            missing_segment = Some(name.to_string());
+                        }
+                        Ok::<(), InversionError>(())
+                    })?;
+                    return Err(InversionError::GfaParse(format!(
+                        "Segment '{}' is used in path '{}' but has no S-line (line {})",
+                        missing_segment.unwrap(),
+                        path.name,
+                        path.line_number
+                    )));
+                }
+                path.steps = CompressedSteps::from(parsed_steps.steps);
+            }
+        }
+
+        // renumber the segments in the order they were first seen, counting both S-lines and paths,
+        // so that the IDs are the same as if the GFA were parsed one line at a time
+        let num_segments = self.segment_names.len();
+        let mut new_ids = vec![0i64; num_segments];
+        let mut num_seen = 0;
+        let mut see = |id: i64| {
+            let new_id = &mut new_ids[id.unsigned_abs() as usize - 1];
+            if *new_id == 0 {
+                num_seen += 1;
+                *new_id = num_seen;
+            }
+        };
+        let mut num_segment_lines_seen = 0;
+        for path in &self.paths {
+            while num_segment_lines_seen < path.num_segments_before {
+                num_segment_lines_seen += 1;
+                see(num_segment_lines_seen as i64);
+            }
+            for step in &path.steps {
+                see(step);
+            }
+        }
+        for id in num_segment_lines_seen + 1..=num_segments {
+            see(id as i64);
+        }
+
+        let is_renumbered = new_ids.iter().zip(1..).any(|(new_id, id)| *new_id != id);
+        let (segment_names, segment_lengths) = if is_renumbered {
+            let mut old_ids = vec![0i64; num_segments];
+            for (old_id, new_id) in (1..).zip(&new_ids) {
+                old_ids[*new_id as usize - 1] = old_id;
+            }
+            let mut segment_names = SegmentNames::new();
+            for old_id in &old_ids {
+                segment_names.intern(self.segment_names.name(*old_id).unwrap())?;
+            }
+            self.paths.par_iter_mut().for_each(|path| {
+                path.steps = path
+                    .steps
+                    .iter()
+                    .map(|step| step.signum() * new_ids[step.unsigned_abs() as usize - 1])
+                    .collect();
+            });
+            let segment_lengths = old_ids
+                .iter()
+                .map(|old_id| self.segment_lengths[*old_id as usize - 1])
+                .collect();
+            (segment_names, segment_lengths)
+        } else {
+            (self.segment_names, self.segment_lengths)
+        };
+
+        let paths = self
+            .paths
+            .into_iter()
+            .map(|path| GraphPath::new(&path.name, path.steps, path.metadata))
+            .collect();
+        PangenomeGraph::new(segment_names, segment_lengths, paths)
     }
-    return Ok(segment_positions);
 }
 
+/// Split a line of a GFA into fields, returning `None` if it isn't a line we need.
+fn parse_gfa_line<'a>(
+    line: &'a [u8],
+    line_number: usize,
+    filter: &PathFilter,
+) -> Result<Option<GfaLine<'a>>, InversionError> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let line = std::str::from_utf8(line).map_err(|_| {
+        InversionError::GfaParse("Reading error: stream did not contain valid UTF-8".to_string())
+    })?;
+    let fields: Vec<&str> = line.split("\t").collect();
+
+    match fields[0] {
+        "S" => {
+            if fields.len() < 3 {
+                return Err(InversionError::GfaParse(format!(
+                    "S-line on line {} has {} fields but should have at least 3",
+                    line_number,
+                    fields.len()
+                )));
+            }
+            let length = parse_segment_length(&fields).map_err(|err| {
+                InversionError::GfaParse(format!("{} (line {})", err, line_number))
+            })?;
+            Ok(Some(GfaLine::Segment {
+                name: fields[1],
+                length,
+            }))
+        }
+        "P" => {
+            if fields.len() < 3 {
+                return Err(InversionError::GfaParse(format!(
+                    "P-line on line {} has {} fields but should have at least 3",
+                    line_number,
+                    fields.len()
+                )));
+            }
+            if !filter.keeps(fields[1]) {
+                return Ok(Some(GfaLine::FilteredPath));
+            }
+            Ok(Some(GfaLine::Path(PathLine {
+                line_number,
+                name: fields[1].to_string(),
+                steps: fields[2],
+                format: StepFormat::Path,
+                overlaps: fields.get(3).copied().unwrap_or("*"),
+                start_offset: parse_subpath_name(fields[1])
+                    .1
+                    .map(|(start, _)| start)
+                    .unwrap_or(0),
+            })))
+        }
+        "W" => {
+            if fields.len() < 7 {
+                return Err(InversionError::GfaParse(format!(
+                    "W-line on line {} has {} fields but should have at least 7",
+                    line_number,
+                    fields.len()
+                )));
+            }
+            let name = make_walk_name(fields[1], fields[2], fields[3]);
+            if !filter.keeps(&name) {
+                return Ok(Some(GfaLine::FilteredPath));
+            }
+            let start_offset = if fields[4] == "*" {
+                0
+            } else {
+                fields[4].parse().map_err(|_| {
+                    InversionError::GfaParse(format!(
+                        "Invalid walk start position '{}' on line {}",
+                        fields[4], line_number
+                    ))
+                })?
+            };
+            Ok(Some(GfaLine::Path(PathLine {
+                line_number,
+                name,
+                steps: fields[6],
+                format: StepFormat::Walk,
+                overlaps: "*",
+                start_offset,
+            })))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parse the steps and overlaps of a path using the segment IDs assigned so far.
+fn parse_path_line(
+    path_line: PathLine,
+    num_segments_before: usize,
+    segment_names: &SegmentNames,
+) -> Result<ParsedPath, InversionError> {
+    let add_line_number = |err: InversionError| {
+        InversionError::GfaParse(format!("{} (line {})", err, path_line.line_number))
+    };
+    let steps = parse_steps_parallel(path_line.steps, path_line.format, segment_names)
+        .map_err(add_line_number)?;
+    let overlaps =
+        parse_gfa_overlaps(path_line.overlaps, steps.steps.len()).map_err(add_line_number)?;
+    Ok(ParsedPath {
+        unparsed_steps: steps
+            .has_unknown_segments
+            .then(|| (path_line.steps.to_string(), path_line.format)),
+        name: path_line.name,
+        line_number: path_line.line_number,
+        steps: CompressedSteps::from(steps.steps),
+        metadata: PathMetadata {
+            start_offset: path_line.start_offset,
+            overlaps,
+        },
+        num_segments_before,
+    })
+}
+
+fn make_duplicate_path_error(path_name: &str, line_number: usize) -> InversionError {
+    InversionError::GfaParse(format!(
+        "Path '{}' on line {} has the same name as an earlier path",
+        path_name, line_number
+    ))
+}
+
+#[cfg(test)]
 mod tests {
+    use super::*;
+    use flate2::{Compression as GzCompression, write::GzEncoder};
+    use std::io::Write;
+
+    const TEST_GFA: &str = concat!(
+        "H\tVN:Z:1.1\n",
+        "S\t1\tACGT\n",
+        "S\t2\t*\tLN:i:2\n",
+        "P\tsampleA#0#chr1\t1+,2-\t*\n",
+        "P\tsampleC#0#chr1[100-106]\t1+,2-\t*\n",
+        "W\tsampleB\t1\tchr1\t10\t16\t<2<1\n",
+    );
+
+    fn write_test_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}_{}", std::process::id(), name));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_compressed_gfa() {
+        let mut gzip_encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+        gzip_encoder.write_all(TEST_GFA.as_bytes()).unwrap();
+        let gzip_bytes = gzip_encoder.finish().unwrap();
+
+        let mut bgzf_writer = bgzf::io::Writer::new(Vec::new());
+        bgzf_writer.write_all(TEST_GFA.as_bytes()).unwrap();
+        let bgzf_bytes = bgzf_writer.finish().unwrap();
+
+        assert_eq!(detect_compression(TEST_GFA.as_bytes()), Compression::None);
+        assert_eq!(detect_compression(&gzip_bytes), Compression::Gzip);
+        assert_eq!(detect_compression(&bgzf_bytes), Compression::Bgzf);
+
+        for (name, contents) in [
+            ("test.gfa", TEST_GFA.as_bytes()),
+            ("test.gfa.gz", &gzip_bytes),
+            ("test.gfa.bgz", &bgzf_bytes),
+        ] {
+            let path = write_test_file(name, contents);
+            let graph = read_gfa(path.clone()).unwrap();
+            std::fs::remove_file(path).unwrap();
+            assert_eq!(graph.num_segments(), 2);
+            assert_eq!(graph.segment_length(1).unwrap(), 4);
+            assert_eq!(graph.segment_length(2).unwrap(), 2);
+            let path_names: Vec<&str> = graph.paths().iter().map(|path| path.name()).collect();
+            assert_eq!(
+                path_names,
+                vec![
+                    "sampleA#0#chr1",
+                    "sampleC#0#chr1[100-106]",
+                    "sampleB#1#chr1"
+                ]
+            );
+            assert_eq!(
+                graph.path("sampleA#0#chr1").unwrap().steps().to_vec(),
+                vec![1, -2]
+            );
+            let walk = graph.path("sampleB#1#chr1").unwrap();
+            assert_eq!(walk.steps().to_vec(), vec![-2, -1]);
+            assert_eq!(walk.metadata().start_offset, 10);
+            assert_eq!(
+                graph
+                    .path("sampleC#0#chr1[100-106]")
+                    .unwrap()
+                    .metadata()
+                    .start_offset,
+                100
+            );
+        }
+    }
+
     #[test]
     #[should_panic]
     fn test_parse_bad_gfa_path() {
-        super::parse_gfa_path("1-,2+,3-,235").unwrap();
+        super::parse_gfa_path("1-,2+,3-,235", &mut SegmentNames::new()).unwrap();
+    }
+
+    #[test]
+    fn test_parse_bad_gfa_walk() {
+        let mut segment_names = SegmentNames::new();
+        assert!(parse_gfa_walk(">1<<2", &mut segment_names).is_err());
+        assert!(parse_gfa_walk(">1<2>", &mut segment_names).is_err());
+        assert!(parse_gfa_walk("1<2", &mut segment_names).is_err());
+        assert!(parse_gfa_walk("", &mut segment_names).is_err());
+    }
+
+    #[test]
+    fn test_parse_gfa_overlaps() {
+        assert_eq!(
+            parse_gfa_overlaps("3M4D2=1X,*,10S5I", 4).unwrap(),
+            vec![6, 0, 5]
+        );
+        for cigar in ["M", "3", "3Q", "", "3M4", "M3", "99999999999999999999M"] {
+            assert!(parse_gfa_overlaps(cigar, 2).is_err(), "{}", cigar);
+        }
+    }
+
+    #[test]
+    fn test_numeric_segment_names() {
+        let mut segment_names = SegmentNames::new();
+        assert_eq!(segment_names.intern("5").unwrap(), 1);
+        assert_eq!(segment_names.intern("0").unwrap(), 2);
+        assert_eq!(segment_names.id("5"), Some(1));
+        assert_eq!(segment_names.id("05"), None);
+        assert_eq!(segment_names.id("3"), None);
+        assert_eq!(segment_names.intern("s5").unwrap(), 3);
+        assert_eq!(segment_names.intern("5").unwrap(), 1);
+        assert_eq!(segment_names.id("0"), Some(2));
+        assert_eq!(segment_names.id("s5"), Some(3));
+        assert_eq!(segment_names.name(3), Some("s5"));
+    }
+
+    #[test]
+    fn test_parse_long_path() {
+        let mut segment_names = SegmentNames::new();
+        let steps: Vec<(String, bool)> = (0..200000)
+            .map(|i| (format!("s{}", i % 1000), i % 3 == 0))
+            .collect();
+        let path_string = steps
+            .iter()
+            .map(|(name, is_reverse)| format!("{}{}", name, if *is_reverse { "-" } else { "+" }))
+            .collect::<Vec<_>>()
+            .join(",");
+        let walk_string: String = steps
+            .iter()
+            .map(|(name, is_reverse)| format!("{}{}", if *is_reverse { "<" } else { ">" }, name))
+            .collect();
+        let path = parse_gfa_path(&path_string, &mut segment_names).unwrap();
+        assert_eq!(path.len(), steps.len());
+        assert_eq!(
+            parse_gfa_walk(&walk_string, &mut segment_names).unwrap(),
+            path
+        );
+
+        let parsed = parse_steps_parallel(&path_string, StepFormat::Path, &segment_names).unwrap();
+        assert_eq!(parsed.steps, path);
+        assert!(!parsed.has_unknown_segments);
+        let parsed = parse_steps_parallel(&walk_string, StepFormat::Walk, &segment_names).unwrap();
+        assert_eq!(parsed.steps, path);
+
+        // errors are the same as they would be without splitting the path up
+        for (steps, format) in [
+            (path_string.clone() + ",", StepFormat::Path),
+            (path_string.replacen("s999+", "s999", 1), StepFormat::Path),
+            (walk_string.clone() + ">", StepFormat::Walk),
+            (walk_string.replacen(">s999", "><s999", 1), StepFormat::Walk),
+        ] {
+            let error = parse_steps(&steps, format, &segment_names).err().unwrap();
+            let parallel_error = parse_steps_parallel(&steps, format, &segment_names)
+                .err()
+                .unwrap();
+            assert_eq!(error.to_string(), parallel_error.to_string());
+        }
+    }
+
+    #[test]
+    fn test_read_gfa_filtered() {
+        let path = write_test_file(
+            "test_filtered.gfa",
+            concat!(
+                "S\t1\tACGT\n",
+                "P\tsampleA#0#chr1\t1+\t*\n",
+                "P\tsampleB#0#chr1\t1+,2+\t*\n",
+                "W\tsampleC\t1\tchr1\t0\t4\tnot a walk\n",
+                "P\tother#0#chr1\t1-\t*\n",
+            )
+            .as_bytes(),
+        );
+        let filter = PathFilter::parse(&["sample*"], &["sampleB", "re:C#1"]).unwrap();
+        let graph = read_gfa_filtered(path.clone(), &filter);
+        let unfiltered_graph = read_gfa(path.clone());
+        std::fs::remove_file(path).unwrap();
+
+        // the paths that are filtered out aren't parsed, so their problems don't matter
+        assert!(unfiltered_graph.is_err());
+        let path_names: Vec<&str> = graph
+            .as_ref()
+            .unwrap()
+            .paths()
+            .iter()
+            .map(|path| path.name())
+            .collect();
+        assert_eq!(path_names, vec!["sampleA#0#chr1"]);
+    }
+
+    #[test]
+    fn test_read_gfa_segment_names() {
+        let path = write_test_file(
+            "test_names.gfa",
+            concat!(
+                "P\tsampleA#0#chr1\tutg1+,utg20-,utg1+\t*\n",
+                "S\tutg20\tAC\n",
+                "S\tutg1\tACGT\n",
+                "W\tsampleB\t1\tchr1\t0\t6\t>utg1<utg20\n",
+            )
+            .as_bytes(),
+        );
+        let graph = read_gfa(path.clone()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            graph.path("sampleA#0#chr1").unwrap().steps().to_vec(),
+            vec![1, -2, 1]
+        );
+        assert_eq!(
+            graph.path("sampleB#1#chr1").unwrap().steps().to_vec(),
+            vec![1, -2]
+        );
+        assert_eq!(graph.segment_length(1).unwrap(), 4);
+        assert_eq!(graph.segment_length(2).unwrap(), 2);
+        assert_eq!(graph.segment_names().name(1), Some("utg1"));
+        assert_eq!(graph.segment_names().name(-2), Some("utg20"));
+
+        let path = write_test_file(
+            "test_missing_segment.gfa",
+            "S\ts1\tACGT\nP\tsampleA#0#chr1\ts1+,s2+\t*\n".as_bytes(),
+        );
+        let result = read_gfa(path.clone());
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error parsing GFA: Segment 's2' is used in path 'sampleA#0#chr1' but has no S-line \
+             (line 2)"
+        );
     }
 }
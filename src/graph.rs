@@ -0,0 +1,659 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use crate::InversionError;
+use crate::compressed_steps::CompressedSteps;
+use crate::gfa::{PathMetadata, SegmentNames};
+use crate::pansn::PanSnName;
+
+/// A path through a pangenome graph
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphPath {
+    name: String,
+    steps: CompressedSteps,
+    metadata: PathMetadata,
+}
+
+impl GraphPath {
+    /// Make a path from its name, its steps as segment IDs with orientation indicated by sign, and
+    /// its metadata.
+    pub fn new(name: &str, steps: impl Into<CompressedSteps>, metadata: PathMetadata) -> Self {
+        Self {
+            name: name.to_string(),
+            steps: steps.into(),
+            metadata,
+        }
+    }
+
+    /// Name of the path, e.g., `sampleA#1#chr3`, including any subpath range
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Segments traversed by the path, with orientation indicated by sign
+    pub fn steps(&self) -> &CompressedSteps {
+        &self.steps
+    }
+
+    /// Start position of the path on its sequence and the overlaps between its steps
+    pub fn metadata(&self) -> &PathMetadata {
+        &self.metadata
+    }
+
+    /// Steps of the reverse complement of the path
+    pub fn reversed_steps(&self) -> CompressedSteps {
+        self.steps.reverse_complement()
+    }
+
+    /// Name of the path split into its PanSN sample, haplotype, contig, and range
+    pub fn pansn_name(&self) -> PanSnName<'_> {
+        PanSnName::parse(&self.name)
+    }
+
+    /// Check whether the path matches a name given by the user, which can be the name of the path
+    /// with or without a subpath range, the name of its sample and haplotype, or the name of its
+    /// sample.
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.pansn_name().matches(name)
+    }
+}
+
+/// A pangenome graph, keeping only what we need to look for inversions: the lengths and names of
+/// the segments, and the paths through them.
+///
+/// Segments are referred to by the dense integer IDs assigned to them by `SegmentNames`, so their
+/// lengths are stored in a vector rather than a map. Paths are kept in the order they were added.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::gfa::PathMetadata;
+/// use inversion_finder::graph::{GraphPath, PangenomeGraph};
+///
+/// let graph = PangenomeGraph::from_segment_lengths(
+///     vec![100, 20, 100],
+///     vec![
+///         GraphPath::new("a#1#chr1", vec![1, 2, 3], PathMetadata::default()),
+///         GraphPath::new("b#1#chr1:500-700", vec![1, -2, 3], PathMetadata::default()),
+///     ],
+/// )
+/// .unwrap();
+/// assert_eq!(graph.segment_length(-2).unwrap(), 20);
+/// assert_eq!(graph.segment_names().name(3), Some("3"));
+/// assert_eq!(graph.path("a#1#chr1").unwrap().steps().to_vec(), vec![1, 2, 3]);
+/// assert_eq!(graph.matching_paths("b#1#chr1").count(), 1);
+/// assert_eq!(graph.paths()[1].pansn_name().contig, "chr1");
+/// ```
+#[derive(Debug)]
+pub struct PangenomeGraph {
+    segment_names: SegmentNames,
+
+    /// length in bp of each segment, where the length of the segment with ID `i` is at index `i-1`
+    segment_lengths: Vec<i64>,
+
+    /// length in bp of each segment not counting its overlaps with preceding steps, used for
+    /// scoring alignments
+    scoring_segment_lengths: Vec<i64>,
+
+    paths: Vec<GraphPath>,
+
+    /// map of path name to index in `paths`
+    path_indices: HashMap<String, usize>,
+
+    /// index of the positions of the steps of each path in `paths`, built the first time it's
+    /// needed
+    position_indices: Vec<OnceLock<PathPositionIndex>>,
+}
+
+impl PangenomeGraph {
+    /// Make a graph out of its segments and paths.
+    ///
+    /// # Arguments
+    ///
+    /// * `segment_names`: table of the names of the segments
+    /// * `segment_lengths`: length in bp of each segment, in order of segment ID starting from 1
+    /// * `paths`: paths through the graph, in the order they should be accessed
+    pub fn new(
+        segment_names: SegmentNames,
+        segment_lengths: Vec<i64>,
+        paths: Vec<GraphPath>,
+    ) -> Result<Self, InversionError> {
+        if segment_lengths.len() != segment_names.len() {
+            return Err(InversionError::GfaParse(format!(
+                "Graph has {} segment names but {} segment lengths",
+                segment_names.len(),
+                segment_lengths.len()
+            )));
+        }
+
+        let mut path_indices = HashMap::new();
+        for (i, path) in paths.iter().enumerate() {
+            if let Some(segment) = path
+                .steps
+                .iter()
+                .find(|segment| segment_index(*segment) >= segment_lengths.len())
+            {
+                return Err(segment_not_found(&segment_names, segment));
+            }
+            if path_indices.insert(path.name.clone(), i).is_some() {
+                return Err(InversionError::GfaParse(format!(
+                    "There is more than one path named '{}'",
+                    path.name
+                )));
+            }
+        }
+
+        // bases in overlaps between steps shouldn't count twice towards alignment scores
+        let scoring_segment_lengths = effective_segment_lengths(&segment_lengths, &paths);
+
+        Ok(Self {
+            segment_names,
+            segment_lengths,
+            scoring_segment_lengths,
+            position_indices: paths.iter().map(|_| OnceLock::new()).collect(),
+            paths,
+            path_indices,
+        })
+    }
+
+    /// Make a graph whose segments are named after their IDs, i.e., segment 1 is named "1".
+    ///
+    /// # Arguments
+    ///
+    /// * `segment_lengths`: length in bp of each segment, in order of segment ID starting from 1
+    /// * `paths`: paths through the graph, in the order they should be accessed
+    pub fn from_segment_lengths(
+        segment_lengths: Vec<i64>,
+        paths: Vec<GraphPath>,
+    ) -> Result<Self, InversionError> {
+        let mut segment_names = SegmentNames::new();
+        for i in 1..=segment_lengths.len() {
+            segment_names.intern(&i.to_string())?;
+        }
+        Self::new(segment_names, segment_lengths, paths)
+    }
+
+    /// Number of segments in the graph
+    pub fn num_segments(&self) -> usize {
+        self.segment_lengths.len()
+    }
+
+    /// Get the length in bp of a segment, ignoring the orientation encoded in the sign of `id`.
+    pub fn segment_length(&self, id: i64) -> Result<i64, InversionError> {
+        self.segment_lengths
+            .get(segment_index(id))
+            .copied()
+            .ok_or_else(|| segment_not_found(&self.segment_names, id))
+    }
+
+    /// Get the length in bp of a segment to use when scoring alignments, which doesn't count bases
+    /// that are already accounted for by overlapping preceding steps.
+    pub fn scoring_segment_length(&self, id: i64) -> Result<i64, InversionError> {
+        self.scoring_segment_lengths
+            .get(segment_index(id))
+            .copied()
+            .ok_or_else(|| segment_not_found(&self.segment_names, id))
+    }
+
+    /// Table of the original names of the segments
+    pub fn segment_names(&self) -> &SegmentNames {
+        &self.segment_names
+    }
+
+    /// All paths in the graph, in the order they were added
+    pub fn paths(&self) -> &[GraphPath] {
+        &self.paths
+    }
+
+    /// Get a path by its exact name.
+    pub fn path(&self, name: &str) -> Option<&GraphPath> {
+        self.path_indices.get(name).map(|i| &self.paths[*i])
+    }
+
+    /// Get a path by its exact name, or an error if there is no such path.
+    pub fn get_path(&self, name: &str) -> Result<&GraphPath, InversionError> {
+        self.path(name)
+            .ok_or(InversionError::PathNotFound(name.to_string()))
+    }
+
+    /// Iterate over the paths matching a name given by the user, in order. This can be the name of
+    /// a path with or without a subpath range, or the name of a PanSN sample or sample and
+    /// haplotype (e.g., `sampleA#1`) to get all of its paths.
+    pub fn matching_paths<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a GraphPath> {
+        self.paths.iter().filter(move |path| path.matches(name))
+    }
+
+    /// Choose the paths to use as references from a name given by the user.
+    ///
+    /// The name is first matched against the names of paths, with or without their subpath
+    /// ranges, and then against PanSN sample and haplotype names like `sampleA#1`, and then against
+    /// sample names. A sample name must only match paths of a single haplotype, because otherwise
+    /// it isn't clear which haplotype to use. Only the first path of each contig and subpath range
+    /// start is used, in case a region was extracted from a graph more than once.
+    ///
+    /// # Returns
+    ///
+    /// * the reference paths in the order they appear in the graph, or an error listing the
+    ///   haplotypes the name could have meant if it is missing or ambiguous
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use inversion_finder::gfa::PathMetadata;
+    /// use inversion_finder::graph::{GraphPath, PangenomeGraph};
+    ///
+    /// let paths = ["a#1#chr1", "a#1#chr2", "b#1#chr1", "b#2#chr1"]
+    ///     .iter()
+    ///     .map(|name| GraphPath::new(name, vec![1], PathMetadata::default()))
+    ///     .collect();
+    /// let graph = PangenomeGraph::from_segment_lengths(vec![100], paths).unwrap();
+    /// assert_eq!(graph.reference_paths("a").unwrap().len(), 2);
+    /// assert_eq!(graph.reference_paths("b#2").unwrap()[0].name(), "b#2#chr1");
+    /// assert_eq!(graph.reference_paths("b#1#chr1").unwrap()[0].name(), "b#1#chr1");
+    /// assert!(graph.reference_paths("b").is_err());
+    /// assert!(graph.reference_paths("c").is_err());
+    /// ```
+    pub fn reference_paths(&self, name: &str) -> Result<Vec<&GraphPath>, InversionError> {
+        let mut matches: Vec<&GraphPath> = self
+            .paths
+            .iter()
+            .filter(|path| path.name == name || path.pansn_name().name_without_range() == name)
+            .collect();
+        if matches.is_empty() {
+            matches = self
+                .paths
+                .iter()
+                .filter(|path| {
+                    let pansn_name = path.pansn_name();
+                    pansn_name.is_pansn() && pansn_name.haplotype_name() == name
+                })
+                .collect();
+        }
+        if matches.is_empty() {
+            matches = self
+                .paths
+                .iter()
+                .filter(|path| path.pansn_name().sample == name)
+                .collect();
+            let haplotypes = distinct_haplotypes(&matches);
+            if haplotypes.len() > 1 {
+                return Err(InversionError::AmbiguousReference(
+                    name.to_string(),
+                    haplotypes,
+                ));
+            }
+        }
+        if matches.is_empty() {
+            let paths: Vec<&GraphPath> = self.paths.iter().collect();
+            return Err(InversionError::ReferenceNotFound(
+                name.to_string(),
+                distinct_haplotypes(&paths),
+            ));
+        }
+
+        let mut seen_subpaths = HashSet::new();
+        matches.retain(|path| {
+            let pansn_name = path.pansn_name();
+            seen_subpaths.insert((pansn_name.contig, pansn_name.range.map(|(start, _)| start)))
+        });
+        Ok(matches)
+    }
+
+    /// Get the 1-based start and end positions of some steps of a path on its sequence, in the same
+    /// order as `steps`, or `None` for steps that aren't in the path.
+    ///
+    /// This walks along the path once, only as far as the last of the steps, without building a
+    /// `PathPositionIndex`, so it's cheaper than `position_index` for a path whose positions are
+    /// only needed a few times, like a query path that is only aligned once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use inversion_finder::gfa::PathMetadata;
+    /// use inversion_finder::graph::{GraphPath, PangenomeGraph};
+    ///
+    /// let graph = PangenomeGraph::from_segment_lengths(vec![100; 3], Vec::new()).unwrap();
+    /// let path = GraphPath::new(
+    ///     "a",
+    ///     vec![1, -2, 3],
+    ///     PathMetadata { start_offset: 1000, overlaps: vec![10, 0] },
+    /// );
+    /// assert_eq!(
+    ///     graph.step_intervals(&path, &[2, 0, 3]).unwrap(),
+    ///     vec![Some((1191, 1290)), Some((1001, 1100)), None]
+    /// );
+    /// ```
+    pub fn step_intervals(
+        &self,
+        path: &GraphPath,
+        steps: &[usize],
+    ) -> Result<Vec<Option<(i64, i64)>>, InversionError> {
+        let mut order: Vec<usize> = (0..steps.len()).collect();
+        order.sort_by_key(|i| steps[*i]);
+        let mut order = order.into_iter().peekable();
+        let mut intervals = vec![None; steps.len()];
+
+        let mut current_position = path.metadata.start_offset;
+        for (step, segment) in path.steps.iter().enumerate() {
+            if order.peek().is_none() {
+                break;
+            }
+            let end = step_end(self, current_position, segment)?;
+            while let Some(i) = order.next_if(|i| steps[*i] == step) {
+                intervals[i] = Some((current_position + 1, end));
+            }
+            current_position = end
+                .checked_sub(*path.metadata.overlaps.get(step).unwrap_or(&0))
+                .ok_or(InversionError::Overflow("position in path".to_string()))?;
+        }
+        Ok(intervals)
+    }
+
+    /// Get the index of the positions of the steps of a path on its sequence.
+    ///
+    /// The index is built the first time it's requested for each path and then kept, so it can be
+    /// shared by every query aligned to the same reference path.
+    pub fn position_index(&self, name: &str) -> Result<&PathPositionIndex, InversionError> {
+        let path_index = *self
+            .path_indices
+            .get(name)
+            .ok_or(InversionError::PathNotFound(name.to_string()))?;
+        if let Some(position_index) = self.position_indices[path_index].get() {
+            return Ok(position_index);
+        }
+        let position_index = PathPositionIndex::new(self, &self.paths[path_index])?;
+        Ok(self.position_indices[path_index].get_or_init(|| position_index))
+    }
+}
+
+/// Index of the positions of the steps of a path on the sequence that it represents, for looking
+/// up the position of a step in constant time or the step at a position by binary search.
+///
+/// Positions are 1-based and take into account the position on its sequence at which the path
+/// starts and the overlaps between its steps.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::gfa::PathMetadata;
+/// use inversion_finder::graph::{GraphPath, PangenomeGraph, PathPositionIndex};
+///
+/// let graph = PangenomeGraph::from_segment_lengths(vec![100; 7], Vec::new()).unwrap();
+/// let path = GraphPath::new("a", vec![1, -2, 3, 4, 5, -6, -7], PathMetadata::default());
+/// let position_index = PathPositionIndex::new(&graph, &path).unwrap();
+/// assert_eq!(position_index.step_interval(1), Some((101, 200)));
+/// assert_eq!(position_index.step_interval(6), Some((601, 700)));
+/// assert_eq!(position_index.step_at(200), Some(1));
+/// assert_eq!(position_index.step_at(201), Some(2));
+/// assert_eq!(position_index.step_at(701), None);
+/// assert_eq!(position_index.steps_in_region(150, 350), 1..4);
+///
+/// let path = GraphPath::new(
+///     "b",
+///     vec![1, -2, 3, 4, 5, -6, -7],
+///     PathMetadata { start_offset: 1000, overlaps: vec![10; 6] },
+/// );
+/// let position_index = PathPositionIndex::new(&graph, &path).unwrap();
+/// assert_eq!(position_index.step_interval(1), Some((1091, 1190)));
+/// assert_eq!(position_index.step_interval(6), Some((1541, 1640)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPositionIndex {
+    /// 0-based start position of each step, which is also the sum of the lengths of the steps
+    /// before it minus their overlaps, plus the start offset of the path
+    starts: Vec<i64>,
+
+    /// overlap in bp between each pair of consecutive steps, or empty if they don't overlap, so
+    /// that the end of each step can be found from the start of the next one rather than storing
+    /// it
+    overlaps: Vec<i64>,
+
+    /// 0-based end position (exclusive) of the last step
+    end: i64,
+}
+
+impl PathPositionIndex {
+    /// Build the position index of a path through a graph.
+    pub fn new(graph: &PangenomeGraph, path: &GraphPath) -> Result<Self, InversionError> {
+        let mut starts = Vec::with_capacity(path.steps.len());
+        let mut current_position = path.metadata.start_offset;
+        let mut end = current_position;
+        for (i, segment) in path.steps.iter().enumerate() {
+            starts.push(current_position);
+            end = step_end(graph, current_position, segment)?;
+            current_position = end
+                .checked_sub(*path.metadata.overlaps.get(i).unwrap_or(&0))
+                .ok_or(InversionError::Overflow("position in path".to_string()))?;
+        }
+        Ok(Self {
+            starts,
+            overlaps: path.metadata.overlaps.clone(),
+            end,
+        })
+    }
+
+    /// Number of steps in the path
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// 0-based end position (exclusive) of a step that is in the path
+    fn step_end(&self, step: usize) -> i64 {
+        match self.starts.get(step + 1) {
+            Some(next_start) => next_start + self.overlaps.get(step).unwrap_or(&0),
+            None => self.end,
+        }
+    }
+
+    /// Get the 1-based start and end positions of a step, or `None` if there is no such step.
+    pub fn step_interval(&self, step: usize) -> Option<(i64, i64)> {
+        Some((*self.starts.get(step)? + 1, self.step_end(step)))
+    }
+
+    /// Get the index of the step at a 1-based position, or `None` if the position is outside of
+    /// the path. Where steps overlap, this is the later of the steps.
+    pub fn step_at(&self, position: i64) -> Option<usize> {
+        let step = self
+            .starts
+            .partition_point(|start| *start < position)
+            .checked_sub(1)?;
+        (position <= self.step_end(step)).then_some(step)
+    }
+
+    /// Get the indices of the steps overlapping a region given in 1-based inclusive coordinates.
+    pub fn steps_in_region(&self, start: i64, end: i64) -> Range<usize> {
+        // the ends of the steps increase along the path like their starts, so the first step
+        // ending at or after `start` can be found by binary search too
+        let (mut first_step, mut after) = (0, self.len());
+        while first_step < after {
+            let middle = first_step + (after - first_step) / 2;
+            if self.step_end(middle) < start {
+                first_step = middle + 1;
+            } else {
+                after = middle;
+            }
+        }
+        let last_step = self.starts.partition_point(|step_start| *step_start < end);
+        first_step..last_step.max(first_step)
+    }
+}
+
+/// Find the 0-based end position (exclusive) of a step starting at `start`.
+fn step_end(graph: &PangenomeGraph, start: i64, segment: i64) -> Result<i64, InversionError> {
+    start
+        .checked_add(graph.segment_length(segment)?)
+        .ok_or(InversionError::Overflow("position in path".to_string()))
+}
+
+/// Get the names of the haplotypes of some paths, in the order they first appear.
+fn distinct_haplotypes(paths: &[&GraphPath]) -> Vec<String> {
+    let mut haplotypes: Vec<String> = Vec::new();
+    for path in paths {
+        let haplotype = path.pansn_name().haplotype_name();
+        if !haplotypes.iter().any(|seen| seen == haplotype) {
+            haplotypes.push(haplotype.to_string());
+        }
+    }
+    haplotypes
+}
+
+/// Get the index in a segment vector of an oriented segment ID. IDs that can't be in the vector
+/// get an index past the end of any vector.
+fn segment_index(id: i64) -> usize {
+    usize::try_from(id.unsigned_abs())
+        .ok()
+        .and_then(|i| i.checked_sub(1))
+        .unwrap_or(usize::MAX)
+}
+
+/// Make the error for a segment that has no length in the graph, naming it by its original name if
+/// it has one. IDs that aren't in the graph at all never had a name, so they're given as IDs.
+fn segment_not_found(segment_names: &SegmentNames, id: i64) -> InversionError {
+    InversionError::SegmentNotFound(match segment_names.name(id) {
+        Some(name) => name.to_string(),
+        None => format!("with ID {}", id.abs()),
+    })
+}
+
+/// Calculate the length of each segment not counting bases it shares with the segment before it.
+///
+/// When the steps of paths overlap, the overlapping bases at the start of a segment are already
+/// accounted for by the previous segment in the path, so counting the full length of every segment
+/// would count them twice. For each segment, this subtracts the smallest overlap with a preceding
+/// step anywhere in the paths, so every segment is still at least 1 bp long. Segments that are
+/// never preceded by another step keep their full lengths.
+fn effective_segment_lengths(segment_lengths: &[i64], paths: &[GraphPath]) -> Vec<i64> {
+    let mut min_overlaps: Vec<Option<i64>> = vec![None; segment_lengths.len()];
+    for path in paths {
+        for (i, segment) in path.steps.iter().enumerate().skip(1) {
+            // a segment that also appears somewhere without overlaps doesn't need to be adjusted
+            let overlap = *path.metadata.overlaps.get(i - 1).unwrap_or(&0);
+            let min_overlap = &mut min_overlaps[segment_index(segment)];
+            *min_overlap = Some(min_overlap.map_or(overlap, |min| min.min(overlap)));
+        }
+    }
+
+    segment_lengths
+        .iter()
+        .zip(min_overlaps)
+        .map(|(length, overlap)| (length - overlap.unwrap_or(0)).max(1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoring_segment_lengths() {
+        let overlapping = |name, steps, overlaps| {
+            GraphPath::new(
+                name,
+                steps,
+                PathMetadata {
+                    start_offset: 0,
+                    overlaps,
+                },
+            )
+        };
+        let graph = PangenomeGraph::from_segment_lengths(
+            vec![100, 100, 100, 100],
+            vec![
+                overlapping("a", vec![1, 2, 3], vec![31, 31]),
+                overlapping("b", vec![1, -3], vec![20]),
+                overlapping("c", vec![3, 4, 1], vec![150, 20]),
+                overlapping("d", vec![2, 4], Vec::new()),
+            ],
+        )
+        .unwrap();
+        let scoring_lengths: Vec<i64> = (1..=4)
+            .map(|id| graph.scoring_segment_length(id).unwrap())
+            .collect();
+        assert_eq!(scoring_lengths, vec![80, 69, 80, 100]);
+        assert_eq!(graph.segment_length(-3).unwrap(), 100);
+    }
+
+    #[test]
+    fn test_bad_graph() {
+        let path = |steps| GraphPath::new("a", steps, PathMetadata::default());
+        assert!(
+            PangenomeGraph::from_segment_lengths(vec![100, 100], vec![path(vec![1, -3])]).is_err()
+        );
+        assert!(
+            PangenomeGraph::from_segment_lengths(vec![100], vec![path(vec![1]), path(vec![-1])])
+                .is_err()
+        );
+        let graph = PangenomeGraph::from_segment_lengths(vec![100], Vec::new()).unwrap();
+        assert!(graph.segment_length(0).is_err());
+        assert_eq!(
+            graph.segment_length(-2).unwrap_err().to_string(),
+            "Cannot find S-line in GFA for segment with ID 2"
+        );
+        assert!(graph.get_path("a").is_err());
+    }
+
+    #[test]
+    fn test_position_index_past_i32() {
+        // chromosomes of some plant genomes are longer than i32::MAX
+        let graph = PangenomeGraph::from_segment_lengths(
+            vec![2_000_000_000, 2_000_000_000, 100],
+            vec![GraphPath::new("a", vec![1, 2, 3], PathMetadata::default())],
+        )
+        .unwrap();
+        let position_index = graph.position_index("a").unwrap();
+        assert_eq!(
+            position_index.step_interval(2),
+            Some((4_000_000_001, 4_000_000_100))
+        );
+        assert_eq!(position_index.step_at(4_000_000_000), Some(1));
+        assert_eq!(position_index.step_at(0), None);
+        assert_eq!(position_index.steps_in_region(1, 4_000_000_001), 0..3);
+        assert_eq!(
+            position_index.steps_in_region(5_000_000_000, 6_000_000_000),
+            3..3
+        );
+        // the index is only built once
+        assert!(std::ptr::eq(
+            position_index,
+            graph.position_index("a").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_position_index_overlaps() {
+        let graph = PangenomeGraph::from_segment_lengths(
+            vec![100, 50, 100],
+            vec![GraphPath::new(
+                "a",
+                vec![1, 2, 3],
+                PathMetadata {
+                    start_offset: 0,
+                    overlaps: vec![20, 10],
+                },
+            )],
+        )
+        .unwrap();
+        let position_index = graph.position_index("a").unwrap();
+        assert_eq!(position_index.step_interval(1), Some((81, 130)));
+        assert_eq!(position_index.step_interval(2), Some((121, 220)));
+        assert_eq!(position_index.step_at(90), Some(1));
+        assert_eq!(position_index.step_at(125), Some(2));
+        assert_eq!(position_index.steps_in_region(1, 80), 0..1);
+        assert_eq!(position_index.steps_in_region(125, 125), 1..3);
+        assert_eq!(position_index.steps_in_region(95, 120), 0..2);
+
+        // looking up a few steps without an index gives the same positions
+        let path = graph.path("a").unwrap();
+        let steps = [2, 0, 1, 2];
+        let intervals: Vec<_> = steps
+            .iter()
+            .map(|step| position_index.step_interval(*step))
+            .collect();
+        assert_eq!(graph.step_intervals(path, &steps).unwrap(), intervals);
+    }
+}
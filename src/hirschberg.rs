@@ -0,0 +1,535 @@
+//! Exact local alignment of long subproblems in linear memory.
+//!
+//! The highmem algorithm keeps the whole traceback matrix, which takes too much memory for long
+//! subproblems, and the lowmem algorithm only keeps two rows of scores but is a banded heuristic
+//! that can miss the best alignment. This finds the same alignment as the highmem algorithm while
+//! only keeping a few rows of scores at a time, in the style of Hirschberg's algorithm, at the
+//! cost of filling in the cells of the matrices about three times over.
+//!
+//! First, the matrices are filled in one row at a time to find where the best alignment ends, with
+//! every cell carrying along the cell where the alignment ending there starts. Then the path of the
+//! alignment between its start and end is found by divide and conquer: the cells between them are
+//! filled in again, this time carrying along the cell where each alignment leaves the middle row,
+//! which splits the path into two halves that are found the same way. Small blocks are aligned with
+//! a full traceback matrix instead. Because the cells are filled in exactly as they are by the
+//! highmem algorithm, ties are broken the same way too, so the alignments are identical.
+
+use crate::InversionError;
+
+use super::{align, argmax};
+use crate::align::{EXTEND_LEFT, EXTEND_UP, NO_GAP_RUN, TracebackState, gap_run_score};
+use crate::graph::PangenomeGraph;
+use crate::scoring::{GapPenalties, PathScores, ScoringScheme};
+use log::debug;
+
+/// Blocks of the matrices with at most this many cells are aligned with a full traceback matrix
+/// rather than being split any further
+const MAX_BLOCK_CELLS: usize = 1 << 16;
+
+/// A cell of the dynamic programming matrices, along with which of the matrices of Gotoh's
+/// algorithm it's in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Node {
+    i: usize,
+    j: usize,
+    state: TracebackState,
+}
+
+/// Where an alignment leaves the middle row of a block, and its score there
+#[derive(Debug, Clone, Copy)]
+struct Crossing {
+    j: usize,
+    state: TracebackState,
+    score: i64,
+}
+
+/// Scores of the best alignments ending at a cell in each of the matrices of Gotoh's algorithm,
+/// each with something carried along from earlier in the alignment
+#[derive(Debug, Clone, Copy)]
+struct Cell<T> {
+    best: (i64, T),
+    gap_up: (i64, T),
+    gap_left: (i64, T),
+}
+
+impl<T: Copy + Default> Cell<T> {
+    /// A cell where only an alignment in the given state, with the given score, can end
+    fn starting_at(state: TracebackState, score: i64) -> Self {
+        let unreachable = (NO_GAP_RUN, T::default());
+        let start = (score, T::default());
+        match state {
+            TracebackState::Best => Cell {
+                best: start,
+                gap_up: unreachable,
+                gap_left: unreachable,
+            },
+            TracebackState::GapUp => Cell {
+                best: unreachable,
+                gap_up: start,
+                gap_left: unreachable,
+            },
+            TracebackState::GapLeft => Cell {
+                best: unreachable,
+                gap_up: unreachable,
+                gap_left: start,
+            },
+        }
+    }
+
+    fn get_mut(&mut self, state: TracebackState) -> &mut (i64, T) {
+        match state {
+            TracebackState::Best => &mut self.best,
+            TracebackState::GapUp => &mut self.gap_up,
+            TracebackState::GapLeft => &mut self.gap_left,
+        }
+    }
+}
+
+/// The paths of an alignment subproblem and the scores of their segments
+struct Subproblem<'a> {
+    path1: &'a [i64],
+    path2: &'a [i64],
+    path1_scores: PathScores,
+    path2_scores: PathScores,
+    gap_open: i64,
+}
+
+impl Subproblem<'_> {
+    /// Score of an alignment starting at cell `(i, j)`, which is the same as in the first row and
+    /// column of the highmem matrices, where only one of the segments is unaligned
+    fn start_score(&self, i: usize, j: usize) -> i64 {
+        if self.path1[i] == self.path2[j] {
+            self.path1_scores.matches[i]
+        } else if i == 0 && j > 0 {
+            self.path2_scores.gaps[j]
+        } else if j == 0 && i > 0 {
+            self.path1_scores.gaps[i]
+        } else {
+            self.path1_scores.gaps[i] + self.path2_scores.gaps[j]
+        }
+    }
+
+    /// Fill in cell `(i, j)` from its neighbours in the same way as
+    /// `align::align_paths_subproblem`, returning it along with its traceback value. Whatever the
+    /// neighbours carry along is carried into the cell from the one that each of its scores comes
+    /// from.
+    fn fill_cell<T: Copy + Default>(
+        &self,
+        (i, j): (usize, usize),
+        start: Option<(i64, T)>,
+        diagonal: Option<&Cell<T>>,
+        above: Option<&Cell<T>>,
+        left: Option<&Cell<T>>,
+    ) -> (Cell<T>, i8) {
+        let unreachable = (NO_GAP_RUN, T::default());
+        let start = start.unwrap_or(unreachable);
+        let mut gap_flags = 0;
+        let (possible_scores, gap_up, gap_left) = if self.path1[i] == self.path2[j] {
+            let match_i = self.path1_scores.matches[i];
+            let from = |cell: Option<&Cell<T>>| {
+                cell.map_or(unreachable, |cell| (cell.best.0 + match_i, cell.best.1))
+            };
+            (
+                [start, from(diagonal), from(above), from(left)],
+                unreachable,
+                unreachable,
+            )
+        } else {
+            let gap_scores = self.path1_scores.gaps[i] + self.path2_scores.gaps[j];
+            let diagonal =
+                diagonal.map_or(unreachable, |cell| (cell.best.0 + gap_scores, cell.best.1));
+            let gap_up = above.map_or(unreachable, |cell| {
+                let (score, extends) = gap_run_score(
+                    cell.best.0,
+                    cell.gap_up.0,
+                    self.gap_open,
+                    self.path1_scores.gap_extensions[i],
+                );
+                if extends {
+                    gap_flags |= EXTEND_UP;
+                    (score, cell.gap_up.1)
+                } else {
+                    (score, cell.best.1)
+                }
+            });
+            let gap_left = left.map_or(unreachable, |cell| {
+                let (score, extends) = gap_run_score(
+                    cell.best.0,
+                    cell.gap_left.0,
+                    self.gap_open,
+                    self.path2_scores.gap_extensions[j],
+                );
+                if extends {
+                    gap_flags |= EXTEND_LEFT;
+                    (score, cell.gap_left.1)
+                } else {
+                    (score, cell.best.1)
+                }
+            });
+            ([start, diagonal, gap_up, gap_left], gap_up, gap_left)
+        };
+
+        let direction = argmax(&possible_scores.map(|(score, _)| score));
+        let traceback_value =
+            i8::try_from(direction).expect("Traceback values must be >=0") | gap_flags;
+        let cell = Cell {
+            best: possible_scores[direction],
+            gap_up,
+            gap_left,
+        };
+        (cell, traceback_value)
+    }
+
+    /// Fill in the matrices one row at a time to find the best local alignment, returning its start
+    /// and end cells and its score.
+    fn find_best_alignment(&self) -> ((usize, usize), (usize, usize), i64) {
+        let mut row_previous: Vec<Cell<(usize, usize)>> = Vec::new();
+        let mut row_current = Vec::with_capacity(self.path2.len());
+        // the first of the best cells in row-major order, as in `align::traceback`
+        let mut max_score = i64::MIN;
+        let mut argmax_score = (0, 0);
+        let mut best_start = (0, 0);
+        for i in 0..self.path1.len() {
+            for j in 0..self.path2.len() {
+                let (cell, _) = self.fill_cell(
+                    (i, j),
+                    Some((self.start_score(i, j), (i, j))),
+                    j.checked_sub(1).and_then(|j| row_previous.get(j)),
+                    row_previous.get(j),
+                    j.checked_sub(1).map(|j| &row_current[j]),
+                );
+                let (score, start) = cell.best;
+                if score > max_score {
+                    max_score = score;
+                    argmax_score = (i, j);
+                    best_start = start;
+                }
+                row_current.push(cell);
+            }
+            std::mem::swap(&mut row_previous, &mut row_current);
+            row_current.clear();
+        }
+        (best_start, argmax_score, max_score)
+    }
+
+    /// Fill in the block of the matrices from `source` to `sink` one row at a time, allowing
+    /// alignments to start only at `source`, with a score of `source_score`. `visit` is called on
+    /// each cell with its traceback value once it has been filled in, and the cell of `sink` is
+    /// returned.
+    fn fill_block<T, F>(&self, source: Node, source_score: i64, sink: Node, mut visit: F) -> Cell<T>
+    where
+        T: Copy + Default,
+        F: FnMut((usize, usize), &mut Cell<T>, i8),
+    {
+        let mut row_previous: Vec<Cell<T>> = Vec::new();
+        let mut row_current = Vec::with_capacity(sink.j - source.j + 1);
+        for i in source.i..=sink.i {
+            for j in source.j..=sink.j {
+                let k = j - source.j;
+                let (mut cell, traceback_value) = if (i, j) == (source.i, source.j) {
+                    (Cell::starting_at(source.state, source_score), 0)
+                } else {
+                    self.fill_cell(
+                        (i, j),
+                        None,
+                        k.checked_sub(1).and_then(|k| row_previous.get(k)),
+                        row_previous.get(k),
+                        k.checked_sub(1).map(|k| &row_current[k]),
+                    )
+                };
+                visit((i, j), &mut cell, traceback_value);
+                row_current.push(cell);
+            }
+            std::mem::swap(&mut row_previous, &mut row_current);
+            row_current.clear();
+        }
+        *row_previous.last().unwrap()
+    }
+
+    /// Find the cells that the best alignment from `source` to `sink` passes through, adding them
+    /// to `cells` in order.
+    ///
+    /// Only alignments starting at `source` are allowed, so the scores of the cells along the best
+    /// alignment are the same as they are in the whole matrix, and no other alignment can beat it
+    /// in a tie that it didn't beat there.
+    fn find_path(
+        &self,
+        source: Node,
+        source_score: i64,
+        sink: Node,
+        max_block_cells: usize,
+        cells: &mut Vec<(usize, usize)>,
+    ) {
+        let num_rows = sink.i - source.i + 1;
+        let num_columns = sink.j - source.j + 1;
+        if num_rows <= 2 || num_rows * num_columns <= max_block_cells {
+            self.find_path_in_block(source, source_score, sink, cells);
+            return;
+        }
+
+        // find the last node of the alignment in the middle row, which all nodes after it carry
+        let middle_row = (source.i + sink.i) / 2;
+        let mut sink_cell = self.fill_block(source, source_score, sink, |(i, j), cell, _| {
+            if i == middle_row {
+                for state in [
+                    TracebackState::Best,
+                    TracebackState::GapUp,
+                    TracebackState::GapLeft,
+                ] {
+                    let (score, crossing) = cell.get_mut(state);
+                    *crossing = Some(Crossing {
+                        j,
+                        state,
+                        score: *score,
+                    });
+                }
+            }
+        });
+        let (_, crossing) = *sink_cell.get_mut(sink.state);
+        let crossing = crossing.expect("Alignments must cross the middle row");
+        let middle = Node {
+            i: middle_row,
+            j: crossing.j,
+            state: crossing.state,
+        };
+        self.find_path(source, source_score, middle, max_block_cells, cells);
+        self.find_path(middle, crossing.score, sink, max_block_cells, cells);
+    }
+
+    /// Find the cells that the best alignment from `source` to `sink` passes through using a full
+    /// traceback matrix of the block between them
+    fn find_path_in_block(
+        &self,
+        source: Node,
+        source_score: i64,
+        sink: Node,
+        cells: &mut Vec<(usize, usize)>,
+    ) {
+        let num_columns = sink.j - source.j + 1;
+        let mut traceback_values = Vec::with_capacity((sink.i - source.i + 1) * num_columns);
+        self.fill_block::<(), _>(source, source_score, sink, |_, _, traceback_value| {
+            traceback_values.push(traceback_value)
+        });
+
+        let (mut i, mut j) = (sink.i, sink.j);
+        let mut state = sink.state;
+        let mut block_cells = vec![(i, j)];
+        while (i, j) != (source.i, source.j) || state != source.state {
+            let traceback_value = traceback_values[(i - source.i) * num_columns + (j - source.j)];
+            (i, j) = align::traceback_step(traceback_value, &mut state, (i, j))
+                .expect("Alignments must start at the source");
+            block_cells.push((i, j));
+        }
+        // the source is already there if this block follows another one
+        if cells.last() == Some(&(source.i, source.j)) {
+            block_cells.pop();
+        }
+        cells.extend(block_cells.into_iter().rev());
+    }
+}
+
+/// Perform an alignment subproblem exactly in linear memory, finding the same alignment as
+/// `align::align_paths_subproblem` with only a few rows of the matrices kept at a time.
+///
+/// # Arguments
+///
+/// * `path1` and `path2`: paths to align
+/// * `graph`: graph containing the segments, for their lengths
+/// * `scoring_scheme`: how to score the segments
+/// * `gap_penalties`: affine gap penalties for runs of unaligned segments
+pub fn align_paths_subproblem_hirschberg(
+    path1: &[i64],
+    path2: &[i64],
+    graph: &PangenomeGraph,
+    scoring_scheme: &dyn ScoringScheme,
+    gap_penalties: GapPenalties,
+) -> Result<align::Alignment, InversionError> {
+    debug!(
+        "Performing exact lowmem alignment of {}:{} to {}:{} ({}x{})",
+        graph.segment_names().format_oriented(path1[0]),
+        graph
+            .segment_names()
+            .format_oriented(path1[path1.len() - 1]),
+        graph.segment_names().format_oriented(path2[0]),
+        graph
+            .segment_names()
+            .format_oriented(path2[path2.len() - 1]),
+        path1.len(),
+        path2.len(),
+    );
+    let alignment = align_with_max_block_cells(
+        path1,
+        path2,
+        graph,
+        scoring_scheme,
+        gap_penalties,
+        MAX_BLOCK_CELLS,
+    )?;
+    debug!(
+        "Finished exact lowmem alignment of length {}x{}",
+        alignment.alignment_path1.len(),
+        alignment.alignment_path2.len()
+    );
+    Ok(alignment)
+}
+
+fn align_with_max_block_cells(
+    path1: &[i64],
+    path2: &[i64],
+    graph: &PangenomeGraph,
+    scoring_scheme: &dyn ScoringScheme,
+    gap_penalties: GapPenalties,
+    max_block_cells: usize,
+) -> Result<align::Alignment, InversionError> {
+    let subproblem = Subproblem {
+        path1,
+        path2,
+        path1_scores: PathScores::new(path1, graph, scoring_scheme, gap_penalties)?,
+        path2_scores: PathScores::new(path2, graph, scoring_scheme, gap_penalties)?,
+        gap_open: gap_penalties.open,
+    };
+    let (start, end, score) = subproblem.find_best_alignment();
+    let source = Node {
+        i: start.0,
+        j: start.1,
+        state: TracebackState::Best,
+    };
+    let sink = Node {
+        i: end.0,
+        j: end.1,
+        state: TracebackState::Best,
+    };
+    let mut cells = Vec::new();
+    subproblem.find_path(
+        source,
+        subproblem.start_score(start.0, start.1),
+        sink,
+        max_block_cells,
+        &mut cells,
+    );
+
+    let mut alignment_path1: Vec<i64> = Vec::new();
+    let mut alignment_path2: Vec<i64> = Vec::new();
+    for (i, j) in cells {
+        if alignment_path1.last() != Some(&path1[i]) {
+            alignment_path1.push(path1[i]);
+        }
+        if alignment_path2.last() != Some(&path2[j]) {
+            alignment_path2.push(path2[j]);
+        }
+    }
+    Ok(align::Alignment {
+        alignment_path1,
+        alignment_path2,
+        path1_start_index: start.0,
+        path1_end_index: end.0,
+        path2_start_index: start.1,
+        path2_end_index: end.1,
+        score,
+        subproblem_size: (path1.len(), path2.len()),
+        algorithm: align::AlignmentAlgorithm::Hirschberg,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring::{SegmentLength, Sublinear};
+
+    #[test]
+    fn test_align_subproblem_hirschberg() {
+        let path1 = vec![2, 3, 4, -5, 6];
+        let path2 = vec![6, 2, 7, -5];
+        let graph =
+            PangenomeGraph::from_segment_lengths(vec![10, 100, 10, 10, 100, 100, 10], Vec::new())
+                .unwrap();
+        let alignment = align_paths_subproblem_hirschberg(
+            &path1,
+            &path2,
+            &graph,
+            &SegmentLength,
+            GapPenalties::default(),
+        )
+        .unwrap();
+        assert_eq!(alignment.alignment_path1, vec![2, 3, 4, -5]);
+        assert_eq!(alignment.alignment_path2, vec![2, 7, -5]);
+        assert_eq!(alignment.path1_start_index, 0);
+        assert_eq!(alignment.path1_end_index, 3);
+        assert_eq!(alignment.path2_start_index, 1);
+        assert_eq!(alignment.path2_end_index, 3);
+        assert_eq!(alignment.score, 170);
+        assert_eq!(alignment.algorithm, align::AlignmentAlgorithm::Hirschberg);
+    }
+
+    #[test]
+    fn test_same_as_highmem() {
+        // random paths over a few segments, so that there are plenty of repeated segments, runs of
+        // unaligned segments, and ties, and blocks small enough that they're split many times
+        let segment_lengths: Vec<i64> = (0..12).map(|i| [10, 50, 100, 300][i % 4]).collect();
+        let graph = PangenomeGraph::from_segment_lengths(segment_lengths, Vec::new()).unwrap();
+        let mut state: u64 = 1;
+        let mut random = |n: u64| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) % n
+        };
+        let sublinear = Sublinear { exponent: 0.5 };
+        let scoring_schemes: [&dyn ScoringScheme; 2] = [&SegmentLength, &sublinear];
+        for case in 0..300 {
+            let mut random_path = |length| -> Vec<i64> {
+                (0..length)
+                    .map(|_| {
+                        let segment = random(12) as i64 + 1;
+                        if random(4) == 0 { -segment } else { segment }
+                    })
+                    .collect()
+            };
+            let path1 = random_path(1 + case % 40);
+            let path2 = random_path(1 + (case * 7) % 30);
+            let scoring_scheme = scoring_schemes[case % 2];
+            let gap_penalties = [
+                GapPenalties::default(),
+                GapPenalties::new(100, 0.1).unwrap(),
+                GapPenalties::new(20, 0.5).unwrap(),
+            ][case % 3];
+            let expected = align::align_paths_subproblem(
+                &path1,
+                &path2,
+                &graph,
+                scoring_scheme,
+                gap_penalties,
+            )
+            .unwrap();
+            for max_block_cells in [1, 20, MAX_BLOCK_CELLS] {
+                let alignment = align_with_max_block_cells(
+                    &path1,
+                    &path2,
+                    &graph,
+                    scoring_scheme,
+                    gap_penalties,
+                    max_block_cells,
+                )
+                .unwrap();
+                let summary = |alignment: &align::Alignment| {
+                    (
+                        alignment.alignment_path1.clone(),
+                        alignment.alignment_path2.clone(),
+                        alignment.path1_start_index,
+                        alignment.path1_end_index,
+                        alignment.path2_start_index,
+                        alignment.path2_end_index,
+                        alignment.score,
+                    )
+                };
+                assert_eq!(
+                    summary(&alignment),
+                    summary(&expected),
+                    "{:?} {:?}",
+                    path1,
+                    path2
+                );
+            }
+        }
+    }
+}
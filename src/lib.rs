@@ -2,8 +2,20 @@ use std::{error, fmt};
 
 pub mod align;
 pub mod alignment_interface;
+pub mod cache;
+pub mod chain;
+pub mod compressed_steps;
 pub mod gfa;
+pub mod graph;
+pub mod hirschberg;
 pub mod lowmem;
+pub mod pansn;
+pub mod path_filter;
+#[cfg(feature = "python")]
+mod python;
+pub mod scoring;
+pub mod validate;
+mod varint;
 
 /// Find the maximum value in a list.
 ///
@@ -12,7 +24,7 @@ pub mod lowmem;
 /// ```
 /// assert_eq!(inversion_finder::amax(&[2,5,3]), 5);
 /// ```
-pub fn amax(a: &[i32]) -> i32 {
+pub fn amax(a: &[i64]) -> i64 {
     *a.iter().max().unwrap()
 }
 
@@ -23,31 +35,70 @@ pub fn amax(a: &[i32]) -> i32 {
 /// ```
 /// assert_eq!(inversion_finder::argmax(&[2,5,3]), 1);
 /// ```
-pub fn argmax(a: &[i32]) -> i32 {
-    (0..a.len())
-        .max_by_key(|x| a[*x])
-        .unwrap()
-        .try_into()
-        .unwrap()
+pub fn argmax(a: &[i64]) -> usize {
+    (0..a.len()).max_by_key(|x| a[*x]).unwrap()
 }
 
 #[derive(Debug)]
 pub enum InversionError {
     GfaParse(String),
-    SegmentNotFound(i32),
+    SegmentNotFound(String),
     PathNotFound(String),
+    Overflow(String),
+    ReferenceNotFound(String, Vec<String>),
+    AmbiguousReference(String, Vec<String>),
+    Cache(String),
+    InvalidPathFilter(String),
+    InvalidScoringScheme(String),
+    InvalidGapPenalties(String),
+    InvalidStrategy(String),
+    InvalidAlignment(String),
+}
+
+/// Format a list of names for an error message, leaving out the end of long lists.
+fn format_candidates(candidates: &[String]) -> String {
+    const MAX_CANDIDATES: usize = 10;
+    if candidates.len() > MAX_CANDIDATES {
+        format!(
+            "{}, and {} more",
+            candidates[..MAX_CANDIDATES].join(", "),
+            candidates.len() - MAX_CANDIDATES
+        )
+    } else {
+        candidates.join(", ")
+    }
 }
 
 impl fmt::Display for InversionError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             InversionError::GfaParse(e) => write!(f, "Error parsing GFA: {}", e),
-            InversionError::SegmentNotFound(segment_id) => {
-                write!(f, "Cannot find S-line in GFA for segment {}", segment_id)
+            InversionError::SegmentNotFound(segment_name) => {
+                write!(f, "Cannot find S-line in GFA for segment {}", segment_name)
             }
             InversionError::PathNotFound(path_id) => {
                 write!(f, "Cannot find P-line in GFA for path {}", path_id)
             }
+            InversionError::Overflow(e) => write!(f, "Integer overflow: {}", e),
+            InversionError::Cache(e) => write!(f, "{}", e),
+            InversionError::InvalidPathFilter(e) => write!(f, "Invalid path filter: {}", e),
+            InversionError::InvalidScoringScheme(e) => write!(f, "Invalid scoring scheme: {}", e),
+            InversionError::InvalidGapPenalties(e) => write!(f, "Invalid gap penalties: {}", e),
+            InversionError::InvalidStrategy(e) => write!(f, "Invalid alignment strategy: {}", e),
+            InversionError::InvalidAlignment(e) => write!(f, "Invalid alignment: {}", e),
+            InversionError::ReferenceNotFound(name, candidates) => write!(
+                f,
+                "Cannot find a path, haplotype, or sample named {} to use as the reference; \
+                 available haplotypes are: {}",
+                name,
+                format_candidates(candidates)
+            ),
+            InversionError::AmbiguousReference(name, candidates) => write!(
+                f,
+                "Reference sample {} has more than one haplotype, so choose one of: {}",
+                name,
+                format_candidates(candidates)
+            ),
         }
     }
 }
@@ -1,10 +1,13 @@
 use crate::InversionError;
 
 use super::{align, amax, argmax};
+use crate::align::{EXTEND_LEFT, EXTEND_UP, NO_GAP_RUN, TracebackState, gap_run_score};
+use crate::graph::PangenomeGraph;
+use crate::scoring::{GapPenalties, PathScores, ScoringScheme};
 use log::debug;
 use std::collections::HashMap;
 
-fn max_and_argmax(a: &[i32]) -> (i32, i32) {
+fn max_and_argmax(a: &[i64]) -> (i64, usize) {
     let mut max = a[0];
     let mut argmax = 0;
     for (i, x) in a.iter().enumerate() {
@@ -13,96 +16,123 @@ fn max_and_argmax(a: &[i32]) -> (i32, i32) {
             argmax = i;
         }
     }
-    return (max, argmax.try_into().expect("i32 overflow"));
+    (max, argmax)
 }
 
-struct InitializeMatricesLowmemResult(Vec<i32>, Vec<i32>, HashMap<(i32, i32), i8>, i32, (i32, i32));
+struct InitializeMatricesLowmemResult(
+    Vec<i64>,
+    Vec<i64>,
+    HashMap<(usize, usize), i8>,
+    i64,
+    (usize, usize),
+);
 
 fn initialize_matrices_lowmem(
-    path1: &[i32],
-    path2: &[i32],
-    segment_lengths: &HashMap<i32, i32>,
-) -> Result<InitializeMatricesLowmemResult, InversionError> {
-    let mut score_row_previous = Vec::<i32>::with_capacity(path2.len());
+    path1: &[i64],
+    path2: &[i64],
+    path1_scores: &PathScores,
+    path2_scores: &PathScores,
+    gap_open: i64,
+) -> InitializeMatricesLowmemResult {
+    let mut score_row_previous = Vec::<i64>::with_capacity(path2.len());
     // this is a sparse representation of the traceback matrix: if a key isn't present, that
     // indicates that the value is actually 0
-    let mut traceback_matrix = HashMap::<(i32, i32), i8>::new();
+    let mut traceback_matrix = HashMap::<(usize, usize), i8>::new();
 
     // fill in the corner
     score_row_previous.push(if path1[0] == path2[0] {
-        *segment_lengths
-            .get(&path1[0].abs())
-            .ok_or(InversionError::SegmentNotFound(path1[0].abs()))?
+        path1_scores.matches[0]
     } else {
-        -1 * (*segment_lengths
-            .get(&path1[0].abs())
-            .ok_or(InversionError::SegmentNotFound(path1[0].abs()))?
-            + *segment_lengths
-                .get(&path2[0].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[0].abs()))?)
+        path1_scores.gaps[0] + path2_scores.gaps[0]
     });
 
     // fill in the rest of the first row
+    let mut gap_run = NO_GAP_RUN;
     for j in 1..path2.len() {
-        let this_cell_score = if path2[j] == path1[0] {
-            *segment_lengths
-                .get(&path2[j].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[j].abs()))?
+        let mut gap_flags = 0;
+        let possible_scores = if path2[j] == path1[0] {
+            gap_run = NO_GAP_RUN;
+            let match_j = path2_scores.matches[j];
+            [
+                match_j,
+                NO_GAP_RUN,
+                NO_GAP_RUN,
+                score_row_previous[j - 1] + match_j,
+            ]
         } else {
-            -1 * *segment_lengths
-                .get(&path2[j].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[j].abs()))?
+            let (score, extends) = gap_run_score(
+                score_row_previous[j - 1],
+                gap_run,
+                gap_open,
+                path2_scores.gap_extensions[j],
+            );
+            gap_run = score;
+            if extends {
+                gap_flags = EXTEND_LEFT;
+            }
+            [path2_scores.gaps[j], NO_GAP_RUN, NO_GAP_RUN, gap_run]
         };
 
-        let possible_scores = [0, -1, -1, score_row_previous[j - 1]];
-        score_row_previous.push(amax(&possible_scores) + this_cell_score);
-        let traceback_value = argmax(&possible_scores).try_into().expect("i32 overflow");
+        score_row_previous.push(amax(&possible_scores));
+        let traceback_value = i8::try_from(argmax(&possible_scores))
+            .expect("Traceback values must be >=0")
+            | gap_flags;
         if traceback_value != 0 {
-            traceback_matrix.insert((0, j.try_into().expect("i32 overflow")), traceback_value);
+            traceback_matrix.insert((0, j), traceback_value);
         }
     }
 
     let (max_score, argmax_score_j) = max_and_argmax(&score_row_previous);
-    let argmax_score: (i32, i32) = (0, argmax_score_j);
+    let argmax_score: (usize, usize) = (0, argmax_score_j);
 
-    let mut score_row_current = Vec::<i32>::with_capacity(path2.len());
     // initialize everything to 0, but these will not actually ever be read. Just for preventing
     // out of bounds errors.
-    for _ in 0..path2.len() {
-        score_row_current.push(0);
-    }
+    let score_row_current = vec![0; path2.len()];
 
-    return Ok(InitializeMatricesLowmemResult(
+    InitializeMatricesLowmemResult(
         score_row_previous,
         score_row_current,
         traceback_matrix,
         max_score,
         argmax_score,
-    ));
+    )
 }
 
 pub fn align_paths_subproblem_lowmem(
-    path1: &[i32],
-    path2: &[i32],
-    segment_lengths: &HashMap<i32, i32>,
+    path1: &[i64],
+    path2: &[i64],
+    graph: &PangenomeGraph,
+    scoring_scheme: &dyn ScoringScheme,
+    gap_penalties: GapPenalties,
     drop: usize,
 ) -> Result<align::Alignment, InversionError> {
     debug!(
         "Performing lowmem alignment of {}:{} to {}:{} ({}x{})",
-        path1[0],
-        path1[path1.len() - 1],
-        path2[0],
-        path2[path2.len() - 1],
+        graph.segment_names().format_oriented(path1[0]),
+        graph
+            .segment_names()
+            .format_oriented(path1[path1.len() - 1]),
+        graph.segment_names().format_oriented(path2[0]),
+        graph
+            .segment_names()
+            .format_oriented(path2[path2.len() - 1]),
         path1.len(),
         path2.len(),
     );
+    let path1_scores = PathScores::new(path1, graph, scoring_scheme, gap_penalties)?;
+    let path2_scores = PathScores::new(path2, graph, scoring_scheme, gap_penalties)?;
+    let gap_open = gap_penalties.open;
     let InitializeMatricesLowmemResult(
         mut score_row_previous,
         mut score_row_current,
         mut traceback_matrix,
         mut max_score,
         mut argmax_score,
-    ) = initialize_matrices_lowmem(&path1, &path2, &segment_lengths)?;
+    ) = initialize_matrices_lowmem(path1, path2, &path1_scores, &path2_scores, gap_open);
+
+    // scores of the best runs of unaligned segments of path1 ending at each cell of the previous
+    // row, and of path2 ending at the previous cell of this row, for Gotoh's algorithm
+    let mut gap_runs_up = vec![NO_GAP_RUN; path2.len()];
 
     let (max_row_drop, max_col_drop) = if path1.len() > path2.len() {
         //(min(drop + path1.len() - path2.len(), drop * 5), drop)
@@ -112,69 +142,103 @@ pub fn align_paths_subproblem_lowmem(
         (drop, drop + path2.len() - path1.len())
     };
 
-    for i in 1..path1.len() {
+    for (i, &segment_i) in path1.iter().enumerate().skip(1) {
         // fill in first column of this row
-        let this_cell_score = if path1[i] == path2[0] {
-            *segment_lengths
-                .get(&path1[i].abs())
-                .ok_or(InversionError::SegmentNotFound(path1[i]))?
+        let match_i = path1_scores.matches[i];
+        let gap_i = path1_scores.gaps[i];
+        let gap_extension_i = path1_scores.gap_extensions[i];
+        let mut gap_flags = 0;
+        let possible_scores = if segment_i == path2[0] {
+            gap_runs_up[0] = NO_GAP_RUN;
+            [
+                match_i,
+                NO_GAP_RUN,
+                score_row_previous[0] + match_i,
+                NO_GAP_RUN,
+            ]
         } else {
-            -1 * *segment_lengths
-                .get(&path1[i].abs())
-                .ok_or(InversionError::SegmentNotFound(path1[i]))?
+            let (score, extends) = gap_run_score(
+                score_row_previous[0],
+                gap_runs_up[0],
+                gap_open,
+                gap_extension_i,
+            );
+            gap_runs_up[0] = score;
+            if extends {
+                gap_flags = EXTEND_UP;
+            }
+            [gap_i, NO_GAP_RUN, gap_runs_up[0], NO_GAP_RUN]
         };
-        let possible_scores = [0, -1, score_row_previous[0], -1];
-        score_row_current[0] = amax(&possible_scores) + this_cell_score;
-        let traceback_value = argmax(&possible_scores).try_into().expect("i32 overflow");
+        score_row_current[0] = amax(&possible_scores);
+        let traceback_value = i8::try_from(argmax(&possible_scores))
+            .expect("Traceback values must be >=0")
+            | gap_flags;
         if traceback_value != 0 {
-            traceback_matrix.insert((i.try_into().expect("i32 overflow"), 0), traceback_value);
+            traceback_matrix.insert((i, 0), traceback_value);
         }
 
         // fill in the rest of this row
-        let len_i = *segment_lengths
-            .get(&path1[i].abs())
-            .ok_or(InversionError::SegmentNotFound(path1[i]))?;
+        let mut gap_run_left = NO_GAP_RUN;
         for j in 1..path2.len() {
-            let len_j = *segment_lengths
-                .get(&path2[j].abs())
-                .ok_or(InversionError::SegmentNotFound(path2[j]))?;
+            let gap_j = path2_scores.gaps[j];
 
             // heuristic: if we are too far from diagonal, leave traceback as implicit 0 and
             // calculate score as if we are starting alignment here regardless of what is in
             // cells nearby
             if ((i > j) && (i - j > max_row_drop)) || ((j > i) && (j - i > max_col_drop)) {
-                score_row_current[j] = if path1[i] == path2[j] {
-                    len_i
+                gap_runs_up[j] = NO_GAP_RUN;
+                gap_run_left = NO_GAP_RUN;
+                score_row_current[j] = if segment_i == path2[j] {
+                    match_i
                 } else {
-                    -len_i - len_j
+                    gap_i + gap_j
                 };
             } else {
-                let possible_scores = if path1[i] == path2[j] {
+                let mut gap_flags = 0;
+                let possible_scores = if segment_i == path2[j] {
+                    gap_runs_up[j] = NO_GAP_RUN;
+                    gap_run_left = NO_GAP_RUN;
                     [
-                        len_i,
-                        score_row_previous[j - 1] + len_i, // come from diagonal
-                        score_row_previous[j] + len_i,     // come from above
-                        score_row_current[j - 1] + len_i,  // come from left
+                        match_i,
+                        score_row_previous[j - 1] + match_i, // come from diagonal
+                        score_row_previous[j] + match_i,     // come from above
+                        score_row_current[j - 1] + match_i,  // come from left
                     ]
                 } else {
+                    let (gap_run_up, extends_up) = gap_run_score(
+                        score_row_previous[j],
+                        gap_runs_up[j],
+                        gap_open,
+                        gap_extension_i,
+                    );
+                    let (score, extends_left) = gap_run_score(
+                        score_row_current[j - 1],
+                        gap_run_left,
+                        gap_open,
+                        path2_scores.gap_extensions[j],
+                    );
+                    gap_runs_up[j] = gap_run_up;
+                    gap_run_left = score;
+                    if extends_up {
+                        gap_flags |= EXTEND_UP;
+                    }
+                    if extends_left {
+                        gap_flags |= EXTEND_LEFT;
+                    }
                     [
-                        -len_i - len_j,
-                        score_row_previous[j - 1] - len_i - len_j,
-                        score_row_previous[j] - len_i,
-                        score_row_current[j - 1] - len_j,
+                        gap_i + gap_j,
+                        score_row_previous[j - 1] + gap_i + gap_j,
+                        gap_run_up,
+                        gap_run_left,
                     ]
                 };
 
                 score_row_current[j] = amax(&possible_scores);
-                let traceback_value = argmax(&possible_scores).try_into().expect("i32 overflow");
+                let traceback_value = i8::try_from(argmax(&possible_scores))
+                    .expect("Traceback values must be >=0")
+                    | gap_flags;
                 if traceback_value != 0 {
-                    traceback_matrix.insert(
-                        (
-                            i.try_into().expect("i32 overflow"),
-                            j.try_into().expect("i32 overflow"),
-                        ),
-                        traceback_value,
-                    );
+                    traceback_matrix.insert((i, j), traceback_value);
                 }
             }
         }
@@ -182,36 +246,37 @@ pub fn align_paths_subproblem_lowmem(
         let (row_max, row_argmax) = max_and_argmax(&score_row_current);
         if row_max > max_score {
             max_score = row_max;
-            argmax_score = (i.try_into().expect("i32 overflow"), row_argmax);
+            argmax_score = (i, row_argmax);
         }
         // now, switch rows. Not beautiful but faster than reallocating memory
-        let score_row_tmp = score_row_previous;
-        score_row_previous = score_row_current;
-        score_row_current = score_row_tmp;
+        std::mem::swap(&mut score_row_previous, &mut score_row_current);
     }
-    let traceback = traceback_lowmem(&path1, &path2, argmax_score, &traceback_matrix);
+    let traceback = traceback_lowmem(path1, path2, max_score, argmax_score, &traceback_matrix);
     debug!(
         "Finished lowmem alignment of length {}x{}",
         traceback.alignment_path1.len(),
         traceback.alignment_path2.len()
     );
-    return Ok(traceback);
+    Ok(traceback)
 }
 
 fn traceback_lowmem(
-    path1: &[i32],
-    path2: &[i32],
-    argmax_score: (i32, i32),
-    traceback_matrix: &HashMap<(i32, i32), i8>,
+    path1: &[i64],
+    path2: &[i64],
+    score: i64,
+    argmax_score: (usize, usize),
+    traceback_matrix: &HashMap<(usize, usize), i8>,
 ) -> align::Alignment {
     let (mut i, mut j) = argmax_score;
     let path1_end_index = i;
+    let path2_end_index = j;
+    let mut state = TracebackState::Best;
     let mut alignment_end_reached = false;
-    let mut alignment_path1: Vec<i32> = Vec::new();
-    let mut alignment_path2: Vec<i32> = Vec::new();
+    let mut alignment_path1: Vec<i64> = Vec::new();
+    let mut alignment_path2: Vec<i64> = Vec::new();
     while !alignment_end_reached {
-        let segment_path1 = path1[<i32 as TryInto<usize>>::try_into(i).unwrap()];
-        let segment_path2 = path2[<i32 as TryInto<usize>>::try_into(j).unwrap()];
+        let segment_path1 = path1[i];
+        let segment_path2 = path2[j];
         if alignment_path1.is_empty() || *alignment_path1.last().unwrap() != segment_path1 {
             alignment_path1.push(segment_path1);
         }
@@ -219,55 +284,56 @@ fn traceback_lowmem(
             alignment_path2.push(segment_path2);
         }
 
-        match traceback_matrix.get(&(i, j)) {
-            Some(1) => {
-                i -= 1;
-                j -= 1;
-            }
-            Some(2) => i -= 1,
-            Some(3) => j -= 1,
+        let traceback_value = traceback_matrix.get(&(i, j)).copied().unwrap_or(0);
+        match align::traceback_step(traceback_value, &mut state, (i, j)) {
+            Some(cell) => (i, j) = cell,
             None => alignment_end_reached = true,
-            Some(x) => panic!("Bad value {} in traceback matrix!", x),
         }
     }
     let path1_start_index = i;
+    let path2_start_index = j;
     alignment_path1.reverse();
     alignment_path2.reverse();
-    return align::Alignment {
+    align::Alignment {
         alignment_path1,
         alignment_path2,
         path1_start_index,
         path1_end_index,
-    };
+        path2_start_index,
+        path2_end_index,
+        score,
+        subproblem_size: (path1.len(), path2.len()),
+        algorithm: align::AlignmentAlgorithm::Lowmem,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scoring::SegmentLength;
 
     #[test]
     fn test_align_subproblem_lowmem() {
         let path1 = vec![2, 3, 4, -5, 6];
         let path2 = vec![6, 2, 7, -5];
-        let mut segment_lengths: HashMap<i32, i32> = HashMap::new();
-        for i in 0..8 {
-            segment_lengths.insert(
-                i,
-                if (path1.contains(&i) || path1.contains(&(-i)))
-                    && (path2.contains(&i) || path2.contains(&(-i)))
-                {
-                    100
-                } else {
-                    10
-                },
-            );
-        }
-        //let (path1_alignment, path2_alignment, path1_start_index, path1_end_index) =
-        let alignment =
-            align_paths_subproblem_lowmem(&path1, &path2, &segment_lengths, 100).unwrap();
+        let graph =
+            PangenomeGraph::from_segment_lengths(vec![10, 100, 10, 10, 100, 100, 10], Vec::new())
+                .unwrap();
+        let alignment = align_paths_subproblem_lowmem(
+            &path1,
+            &path2,
+            &graph,
+            &SegmentLength,
+            GapPenalties::default(),
+            100,
+        )
+        .unwrap();
         assert_eq!(alignment.alignment_path1, vec![2, 3, 4, -5]);
         assert_eq!(alignment.alignment_path2, vec![2, 7, -5]);
         assert_eq!(alignment.path1_start_index, 0);
         assert_eq!(alignment.path1_end_index, 3);
+        assert_eq!(alignment.path2_start_index, 1);
+        assert_eq!(alignment.path2_end_index, 3);
+        assert_eq!(alignment.score, 170);
     }
 }
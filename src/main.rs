@@ -1,36 +1,118 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
+use inversion_finder::alignment_interface::{
+    ChromosomeGroup, InversionSink, InversionTableWriter, QueryInversions,
+};
 use inversion_finder::*;
 use log::info;
-use std::{error::Error, path::PathBuf};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process,
+};
 
 /// Look for inversions in a pangenome graph in GFA format
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// path to input gfa
-    gfa: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// name of reference path
-    ref_path: String,
+    /// path to input gfa, which may be gzip- or bgzip-compressed
+    #[arg(required = true)]
+    gfa: Option<PathBuf>,
+
+    /// name of reference path, or of reference haplotype (e.g., `sample#1`) or single-haplotype
+    /// sample to use all of its paths as references for their chromosomes
+    #[arg(required = true)]
+    ref_path: Option<String>,
 
     /// when aligning paths longer than this, use the lowmem algorithm
     #[arg(short, long, default_value_t = 10000)]
     max_highmem_path_length: usize,
 
-    /// maximum path length to align
+    /// maximum path length to align, unless `--exact-lowmem` is given
     #[arg(short = 'p', long, default_value_t = 100000)]
     max_path_length: usize,
 
     /// minimum length of an inversion in bp for it to be reported
     #[arg(short = 'l', long, default_value_t = 50)]
-    min_inversion_length: i32,
+    min_inversion_length: i64,
 
     /// maximum drop for heuristic in lowmem mode
     #[arg(short = 'd', long, default_value_t = 1000)]
     max_lowmem_drop: usize,
 
-    /// comma-separated list of paths to exclude
+    /// align paths longer than `--max-highmem-path-length` exactly in linear memory rather than
+    /// with the lowmem heuristic, which is slower but finds the same alignments as the highmem
+    /// algorithm, and aligns paths of any length, ignoring `--max-path-length`
+    #[arg(long)]
+    exact_lowmem: bool,
+
+    /// report every non-overlapping local alignment scoring at least this much in each alignment
+    /// subproblem, rather than only the best one, so that separate inversions close together are
+    /// all found
+    #[arg(short = 's', long)]
+    min_alignment_score: Option<i64>,
+
+    /// how to score segments in alignments: `length` to score them by their lengths,
+    /// `capped:<max_length>` to count segments longer than `max_length` as only `max_length` bp
+    /// long, or `sublinear:<exponent>` to penalize unaligned segments by their lengths to the
+    /// power of `exponent` (between 0 and 1)
+    #[arg(long, default_value = "length")]
+    scoring_scheme: String,
+
+    /// penalty for starting a run of consecutive segments of one path that aren't aligned to the
+    /// other path, e.g., an insertion
+    #[arg(long, default_value_t = 0)]
+    gap_open: i64,
+
+    /// factor between 0 and 1 to multiply the scores of the segments in a run of unaligned
+    /// segments by, so that with a gap open penalty and a factor below 1, long insertions cost less
+    /// than scattered unaligned segments
+    #[arg(long, default_value_t = 1.0)]
+    gap_extend_factor: f64,
+
+    /// how to find inversions: `dp` to align each stretch of the paths that could be inverted with
+    /// dynamic programming, or `chain` or `chain:<gap_cost>` to chain the segments that the paths
+    /// share in opposite orientations, which finds inversions of any length quickly but only
+    /// roughly, with a penalty of `gap_cost` (0.5 by default) for every bp between the segments of
+    /// a chain
+    #[arg(long, default_value = "dp")]
+    strategy: String,
+
+    /// with `--strategy chain`, align up to this many steps on either side of each end of a chain
+    /// with dynamic programming to find exactly where the inversion starts and ends
+    #[arg(long)]
+    refine_window: Option<usize>,
+
+    /// also write every inversion call to this file as a tab-separated table with one row per
+    /// call, adding the calls of each query path as soon as it has been aligned rather than once
+    /// its whole chromosome is done
+    #[arg(long)]
+    calls: Option<PathBuf>,
+
+    /// binary cache of the parsed GFA to read instead of parsing it again, which is written if it
+    /// doesn't exist yet or was made from a different GFA
+    #[arg(long)]
+    cache: Option<PathBuf>,
+
+    /// checksum the whole GFA to check whether the cache is up to date, rather than only its size,
+    /// modification time, and first and last blocks, which is much slower for big GFAs
+    #[arg(long)]
+    cache_full_checksum: bool,
+
+    /// comma-separated list of paths, haplotypes, or samples to read from the GFA, which can also
+    /// be sample prefixes ending in `*` (e.g., `HG0*`) or regexes starting with `re:`; by default,
+    /// all paths are read. The reference is always read.
+    #[arg(short, long, default_value = "")]
+    include: String,
+
+    /// comma-separated list of paths, haplotypes, or samples to exclude, in the same format as
+    /// `--include`
     #[arg(short, long, default_value = "")]
     exclude: String,
 
@@ -39,7 +121,16 @@ struct Args {
     verbose: Verbosity<InfoLevel>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check a GFA for problems before looking for inversions in it
+    Validate {
+        /// path to input gfa, which may be gzip- or bgzip-compressed
+        gfa: PathBuf,
+    },
+}
+
+fn main() {
     let args = Args::parse();
 
     stderrlog::new()
@@ -49,38 +140,170 @@ fn main() -> Result<(), Box<dyn Error>> {
         .init()
         .unwrap();
 
-    info!("Reading GFA");
-    let (segment_lengths, paths, path_names) = gfa::read_gfa(args.gfa)?;
-
-    let ref_path_key = if paths.contains_key(&args.ref_path) {
-        args.ref_path
-    } else {
-        paths
-            .keys()
-            .find(|k| k.split("#").collect::<Vec<_>>()[0] == args.ref_path)
-            .unwrap()
-            .clone()
+    let result = match args.command {
+        Some(Command::Validate { gfa }) => validate(gfa),
+        None => find_inversions(args),
     };
+    // print errors with Display rather than Debug so that messages like lists of candidate
+    // references are readable
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
 
+fn validate(gfa: PathBuf) -> Result<(), Box<dyn Error>> {
+    info!("Validating GFA");
+    let issues = validate::validate_gfa(gfa.clone())?;
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    let num_errors = issues
+        .iter()
+        .filter(|issue| issue.severity == validate::Severity::Error)
+        .count();
+    info!(
+        "Found {} errors and {} warnings",
+        num_errors,
+        issues.len() - num_errors
+    );
+    if num_errors > 0 {
+        return Err(format!(
+            "{} failed validation with {} errors",
+            gfa.display(),
+            num_errors
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn find_inversions(args: Args) -> Result<(), Box<dyn Error>> {
+    // clap makes sure that these are present when there's no subcommand
+    let gfa = args.gfa.unwrap();
+    let ref_path = args.ref_path.unwrap();
+
+    // leave out the paths that won't be aligned while reading the GFA so that they don't take up
+    // memory
+    let paths_to_include: Vec<_> = args.include.split(",").collect();
     let paths_to_exclude: Vec<_> = args.exclude.split(",").collect();
+    let path_filter = path_filter::PathFilter::parse(&paths_to_include, &paths_to_exclude)?
+        .with_reference(&ref_path);
+    let scoring_scheme = scoring::parse_scoring_scheme(&args.scoring_scheme)?;
+    let gap_penalties = scoring::GapPenalties::new(args.gap_open, args.gap_extend_factor)?;
+    let strategy = align::parse_strategy(&args.strategy)?;
+
+    info!("Reading GFA");
+    let graph = match args.cache {
+        Some(cache_path) => {
+            let checksum_mode = if args.cache_full_checksum {
+                cache::ChecksumMode::Full
+            } else {
+                cache::ChecksumMode::Quick
+            };
+            cache::read_gfa_cached(gfa, &cache_path, &path_filter, checksum_mode)?
+        }
+        None => gfa::read_gfa_filtered(gfa, &path_filter)?,
+    };
+
+    let chromosome_groups =
+        alignment_interface::group_paths_by_chromosome(&graph, &ref_path, &path_filter)?;
+
+    let alignment_options = alignment_interface::AlignmentOptions {
+        max_highmem_path_length: args.max_highmem_path_length,
+        max_lowmem_drop: args.max_lowmem_drop,
+        exact_lowmem: args.exact_lowmem,
+        max_path_length: args.max_path_length,
+        min_alignment_score: args.min_alignment_score,
+        scoring_scheme: scoring_scheme.as_ref(),
+        gap_penalties,
+        strategy,
+        refine_window: args.refine_window,
+    };
+
+    // each chromosome's rows are printed as soon as it's done, and each query path's calls are
+    // written to the calls file as soon as it's done, so a failure partway through a run only
+    // loses the query path that was being aligned
+    match args.calls {
+        Some(calls_path) => {
+            let table_writer = InversionTableWriter::new(
+                io::stdout().lock(),
+                &chromosome_groups,
+                args.min_inversion_length,
+            )?;
+            let mut call_writer = CallWriter::new(
+                BufWriter::new(File::create(calls_path)?),
+                table_writer,
+                args.min_inversion_length,
+            )?;
+            alignment_interface::align_chromosomes(
+                &graph,
+                &chromosome_groups,
+                alignment_options,
+                &mut call_writer,
+            )
+        }
+        None => alignment_interface::print_collated_inversions(
+            &graph,
+            &chromosome_groups,
+            alignment_options,
+            args.min_inversion_length,
+        ),
+    }
+}
+
+/// Writes the inversion calls of each query path to a table with one row per call as soon as the
+/// query path has been aligned, and passes them on to another sink.
+struct CallWriter<W: Write, S: InversionSink> {
+    out: W,
+    sink: S,
+    min_inversion_length: i64,
+}
+
+impl<W: Write, S: InversionSink> CallWriter<W, S> {
+    /// Make a call writer and write the header of its table.
+    fn new(mut out: W, sink: S, min_inversion_length: i64) -> Result<Self, Box<dyn Error>> {
+        writeln!(
+            out,
+            "chrom\tref_path\tref_start\tref_end\tquery_path\tquery_start\tquery_end\tscore\t\
+             algorithm"
+        )?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            sink,
+            min_inversion_length,
+        })
+    }
+}
+
+impl<W: Write, S: InversionSink> InversionSink for CallWriter<W, S> {
+    fn add_query(&mut self, query_inversions: QueryInversions) -> Result<(), Box<dyn Error>> {
+        for inversion in &query_inversions.inversions {
+            let (ref_start, ref_end) = inversion.ref_interval;
+            if ref_end - ref_start < self.min_inversion_length {
+                continue;
+            }
+            writeln!(
+                self.out,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:?}",
+                query_inversions.chromosome,
+                inversion.ref_path_key,
+                ref_start,
+                ref_end,
+                inversion.query_path_key,
+                inversion.query_interval.0,
+                inversion.query_interval.1,
+                inversion.score,
+                inversion.algorithm,
+            )?;
+        }
+        self.out.flush()?;
+        self.sink.add_query(query_inversions)
+    }
 
-    let (inversions, query_path_keys) = alignment_interface::align_all_queries(
-        &segment_lengths,
-        &paths,
-        &path_names,
-        &paths_to_exclude,
-        &ref_path_key,
-        alignment_interface::AlignmentOptions {
-            max_highmem_path_length: args.max_highmem_path_length,
-            max_lowmem_drop: args.max_lowmem_drop,
-            max_path_length: args.max_path_length,
-        },
-    )?;
-
-    alignment_interface::print_collated_inversions(
-        &inversions,
-        &query_path_keys,
-        &ref_path_key,
-        args.min_inversion_length,
-    )
+    fn finish_chromosome(&mut self, chromosome: &ChromosomeGroup) -> Result<(), Box<dyn Error>> {
+        self.sink.finish_chromosome(chromosome)
+    }
 }
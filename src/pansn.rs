@@ -0,0 +1,124 @@
+use crate::gfa;
+
+/// A path name split up according to the PanSN naming convention, i.e.,
+/// `sample#haplotype#contig`, optionally followed by a subpath range like `:1000-2000` or
+/// `[1000-2000]`.
+///
+/// Names that aren't in PanSN format are treated as a sample with no haplotype whose only contig
+/// has the same name, so that graphs with simple path names like `assembly1` still work.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::pansn::PanSnName;
+///
+/// let name = PanSnName::parse("sampleA#1#chr3:1000-2000");
+/// assert_eq!(name.sample, "sampleA");
+/// assert_eq!(name.haplotype, Some("1"));
+/// assert_eq!(name.contig, "chr3");
+/// assert_eq!(name.range, Some((1000, Some(2000))));
+/// assert_eq!(name.haplotype_name(), "sampleA#1");
+///
+/// let name = PanSnName::parse("assembly1");
+/// assert_eq!(name.sample, "assembly1");
+/// assert_eq!(name.haplotype, None);
+/// assert_eq!(name.contig, "assembly1");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanSnName<'a> {
+    /// name of the sample, e.g., `sampleA`
+    pub sample: &'a str,
+
+    /// haplotype of the sample, e.g., `1`, or `None` if the name isn't in PanSN format
+    pub haplotype: Option<&'a str>,
+
+    /// name of the contig, e.g., `chr3`, which may itself contain `#`
+    pub contig: &'a str,
+
+    /// 0-based start and optional end of the subpath range, if there is one
+    pub range: Option<(i64, Option<i64>)>,
+
+    /// the whole name without the subpath range
+    name: &'a str,
+}
+
+impl<'a> PanSnName<'a> {
+    /// Split a path name into its PanSN parts.
+    pub fn parse(path_name: &'a str) -> Self {
+        let (name, range) = gfa::parse_subpath_name(path_name);
+        let mut fields = name.splitn(3, "#");
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(sample), Some(haplotype), Some(contig)) => Self {
+                sample,
+                haplotype: Some(haplotype),
+                contig,
+                range,
+                name,
+            },
+            _ => Self {
+                sample: name,
+                haplotype: None,
+                contig: name,
+                range,
+                name,
+            },
+        }
+    }
+
+    /// Whether the name was actually in PanSN format
+    pub fn is_pansn(&self) -> bool {
+        self.haplotype.is_some()
+    }
+
+    /// The whole name without the subpath range, e.g., `sampleA#1#chr3`
+    pub fn name_without_range(&self) -> &'a str {
+        self.name
+    }
+
+    /// The sample and haplotype parts of the name, e.g., `sampleA#1`, or the whole name without
+    /// the subpath range if it isn't in PanSN format.
+    pub fn haplotype_name(&self) -> &'a str {
+        match self.haplotype {
+            Some(haplotype) => &self.name[..self.sample.len() + 1 + haplotype.len()],
+            None => self.name,
+        }
+    }
+
+    /// Check whether this name matches a name given by the user, which can be the name without
+    /// the subpath range, the name of its sample and haplotype (e.g., `sampleA#1`), or the name of
+    /// its sample.
+    pub fn matches(&self, query: &str) -> bool {
+        self.name == query || self.haplotype_name() == query || self.sample == query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pansn_name_parts() {
+        let name = PanSnName::parse("sampleA#1#chrUn#12");
+        assert_eq!(name.contig, "chrUn#12");
+        assert_eq!(name.haplotype_name(), "sampleA#1");
+        assert!(name.is_pansn());
+
+        let name = PanSnName::parse("sampleA#1#chr3[100-200]");
+        assert_eq!(name.sample, "sampleA");
+        assert_eq!(name.contig, "chr3");
+        assert_eq!(name.range, Some((100, Some(200))));
+        assert_eq!(name.name_without_range(), "sampleA#1#chr3");
+
+        let name = PanSnName::parse("assembly1[100]");
+        assert!(!name.is_pansn());
+        assert_eq!(name.haplotype_name(), "assembly1");
+        assert_eq!(name.range, Some((100, None)));
+
+        let name = PanSnName::parse("sampleA#1#chr3:100-200");
+        assert!(name.matches("sampleA#1#chr3"));
+        assert!(name.matches("sampleA#1"));
+        assert!(name.matches("sampleA"));
+        assert!(!name.matches("sampleA#2"));
+        assert!(!name.matches("chr3"));
+    }
+}
@@ -0,0 +1,179 @@
+use regex::Regex;
+use std::fmt;
+
+use crate::InversionError;
+use crate::pansn::PanSnName;
+
+/// A pattern for choosing paths by name.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::path_filter::PathPattern;
+///
+/// let pattern = PathPattern::parse("HG0*").unwrap();
+/// assert!(pattern.matches("HG00438#1#chr1"));
+/// assert!(!pattern.matches("NA18906#1#chr1"));
+///
+/// let pattern = PathPattern::parse("re:#chr(1|2)$").unwrap();
+/// assert!(pattern.matches("HG00438#1#chr2"));
+/// assert!(!pattern.matches("HG00438#1#chr12"));
+///
+/// let pattern = PathPattern::parse("HG00438#1").unwrap();
+/// assert!(pattern.matches("HG00438#1#chr1"));
+/// assert!(!pattern.matches("HG00438#2#chr1"));
+/// ```
+#[derive(Debug, Clone)]
+pub enum PathPattern {
+    /// the name of a path, haplotype, or sample, matched in the same way as the reference
+    Name(String),
+
+    /// the start of the names of samples, e.g., `HG0` for every sample whose name starts with it
+    SamplePrefix(String),
+
+    /// a regex that matches somewhere in the name of the path
+    Regex(Regex),
+}
+
+impl PathPattern {
+    /// Parse a pattern given by the user, which can be a regex following `re:`, a sample prefix
+    /// followed by `*`, or otherwise a path, haplotype, or sample name.
+    pub fn parse(pattern: &str) -> Result<Self, InversionError> {
+        if let Some(regex) = pattern.strip_prefix("re:") {
+            Regex::new(regex)
+                .map(Self::Regex)
+                .map_err(|err| InversionError::InvalidPathFilter(err.to_string()))
+        } else if let Some(prefix) = pattern.strip_suffix("*") {
+            Ok(Self::SamplePrefix(prefix.to_string()))
+        } else {
+            Ok(Self::Name(pattern.to_string()))
+        }
+    }
+
+    /// Check whether a path name matches the pattern.
+    pub fn matches(&self, path_name: &str) -> bool {
+        match self {
+            Self::Name(name) => path_name == name || PanSnName::parse(path_name).matches(name),
+            Self::SamplePrefix(prefix) => PanSnName::parse(path_name).sample.starts_with(prefix),
+            Self::Regex(regex) => regex.is_match(path_name),
+        }
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{}", name),
+            Self::SamplePrefix(prefix) => write!(f, "{}*", prefix),
+            Self::Regex(regex) => write!(f, "re:{}", regex.as_str()),
+        }
+    }
+}
+
+/// Filter for choosing which paths to read from a GFA, so that the ones that won't be used don't
+/// take up memory.
+///
+/// A path is kept if it matches any of the patterns to include (or there aren't any) and none of
+/// the patterns to exclude, or if it's one of the reference paths, which are always kept.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::path_filter::PathFilter;
+///
+/// let filter = PathFilter::parse(&["HG0*", "NA18906"], &["HG00438#2"])
+///     .unwrap()
+///     .with_reference("CHM13");
+/// assert!(filter.keeps("HG00438#1#chr1"));
+/// assert!(!filter.keeps("HG00438#2#chr1"));
+/// assert!(filter.keeps("NA18906#1#chr1"));
+/// assert!(!filter.keeps("NA19240#1#chr1"));
+/// assert!(filter.keeps("CHM13#0#chr1"));
+/// assert!(PathFilter::default().keeps("NA19240#1#chr1"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Vec<PathPattern>,
+    exclude: Vec<PathPattern>,
+    references: Vec<String>,
+}
+
+impl PathFilter {
+    pub fn new(include: Vec<PathPattern>, exclude: Vec<PathPattern>) -> Self {
+        Self {
+            include,
+            exclude,
+            references: Vec::new(),
+        }
+    }
+
+    /// Make a filter from patterns given by the user in the format of `PathPattern::parse`,
+    /// ignoring empty ones.
+    pub fn parse<S: AsRef<str>>(include: &[S], exclude: &[S]) -> Result<Self, InversionError> {
+        let parse_patterns = |patterns: &[S]| {
+            patterns
+                .iter()
+                .map(AsRef::as_ref)
+                .filter(|pattern| !pattern.is_empty())
+                .map(PathPattern::parse)
+                .collect::<Result<Vec<PathPattern>, InversionError>>()
+        };
+        Ok(Self::new(
+            parse_patterns(include)?,
+            parse_patterns(exclude)?,
+        ))
+    }
+
+    /// Always keep the paths matching the name of the reference path, haplotype, or sample, even
+    /// if they would otherwise be filtered out.
+    pub fn with_reference(mut self, name: &str) -> Self {
+        self.references.push(name.to_string());
+        self
+    }
+
+    /// Whether the filter keeps every path
+    pub fn keeps_everything(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Check whether to keep a path.
+    pub fn keeps(&self, path_name: &str) -> bool {
+        if self.keeps_everything() {
+            return true;
+        }
+        let pansn_name = PanSnName::parse(path_name);
+        if self
+            .references
+            .iter()
+            .any(|name| path_name == name || pansn_name.matches(name))
+        {
+            return true;
+        }
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(path_name)))
+            && !self.exclude.iter().any(|p| p.matches(path_name))
+    }
+}
+
+/// The filter written out in full, e.g., to check whether a cached graph was read with the same
+/// filter
+impl fmt::Display for PathFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |patterns: &[PathPattern]| {
+            patterns
+                .iter()
+                .map(PathPattern::to_string)
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        if self.keeps_everything() {
+            return Ok(());
+        }
+        write!(
+            f,
+            "include={};exclude={};references={}",
+            join(&self.include),
+            join(&self.exclude),
+            self.references.join(",")
+        )
+    }
+}
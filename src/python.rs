@@ -0,0 +1,389 @@
+//! Python bindings, built with the `python` feature, e.g., using `maturin develop --release`.
+//!
+//! These are meant for exploring alignment parameters interactively on a graph that has already
+//! been loaded, so the graph is read once with `read_gfa` and then passed to the alignment
+//! functions as many times as needed.
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::align::{self, AlignmentAlgorithm};
+use crate::alignment_interface::{self, AlignmentOptions};
+use crate::compressed_steps::CompressedSteps;
+use crate::graph::PangenomeGraph;
+use crate::path_filter::PathFilter;
+use crate::scoring::GapPenalties;
+use crate::{InversionError, cache, gfa, scoring};
+
+impl From<InversionError> for PyErr {
+    fn from(err: InversionError) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+fn algorithm_name(algorithm: AlignmentAlgorithm) -> String {
+    match algorithm {
+        AlignmentAlgorithm::Highmem => "highmem".to_string(),
+        AlignmentAlgorithm::Lowmem => "lowmem".to_string(),
+        AlignmentAlgorithm::Hirschberg => "hirschberg".to_string(),
+        AlignmentAlgorithm::Chain => "chain".to_string(),
+    }
+}
+
+/// A pangenome graph read from a GFA
+#[pyclass(name = "PangenomeGraph", frozen)]
+struct PyPangenomeGraph {
+    graph: PangenomeGraph,
+}
+
+#[pymethods]
+impl PyPangenomeGraph {
+    /// Names of the paths in the graph, in the order they were read
+    #[getter]
+    fn path_names(&self) -> Vec<String> {
+        self.graph
+            .paths()
+            .iter()
+            .map(|path| path.name().to_string())
+            .collect()
+    }
+
+    /// Number of segments in the graph
+    #[getter]
+    fn num_segments(&self) -> usize {
+        self.graph.num_segments()
+    }
+
+    /// Get the steps of a path as segment IDs, with orientation indicated by sign.
+    fn path(&self, name: &str) -> PyResult<Vec<i64>> {
+        Ok(self.graph.get_path(name)?.steps().to_vec())
+    }
+
+    /// Get the length in bp of a segment.
+    fn segment_length(&self, id: i64) -> PyResult<i64> {
+        Ok(self.graph.segment_length(id)?)
+    }
+
+    /// Get the name that a segment has in the GFA.
+    fn segment_name(&self, id: i64) -> Option<String> {
+        self.graph.segment_names().name(id).map(str::to_string)
+    }
+
+    /// Get the ID of a segment from the name it has in the GFA.
+    fn segment_id(&self, name: &str) -> Option<i64> {
+        self.graph.segment_names().id(name)
+    }
+
+    /// Get the 1-based start and end positions of a step of a path, or `None` if there is no such
+    /// step.
+    fn step_interval(&self, path: &str, step: usize) -> PyResult<Option<(i64, i64)>> {
+        Ok(self.graph.position_index(path)?.step_interval(step))
+    }
+
+    /// Get the index of the step of a path at a 1-based position, or `None` if the position is
+    /// outside of the path.
+    fn step_at(&self, path: &str, position: i64) -> PyResult<Option<usize>> {
+        Ok(self.graph.position_index(path)?.step_at(position))
+    }
+
+    /// Get the indices of the steps of a path overlapping a region given in 1-based inclusive
+    /// coordinates, as a `(start, end)` pair where the end is exclusive.
+    fn steps_in_region(&self, path: &str, start: i64, end: i64) -> PyResult<(usize, usize)> {
+        let steps = self.graph.position_index(path)?.steps_in_region(start, end);
+        Ok((steps.start, steps.end))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "PangenomeGraph({} segments, {} paths)",
+            self.graph.num_segments(),
+            self.graph.paths().len()
+        )
+    }
+}
+
+/// An alignment of part of one path to the reverse complement of part of another
+#[pyclass(name = "Alignment", frozen, get_all)]
+struct PyAlignment {
+    alignment_path1: Vec<i64>,
+    alignment_path2: Vec<i64>,
+    path1_start_index: usize,
+    path1_end_index: usize,
+    path2_start_index: usize,
+    path2_end_index: usize,
+    score: i64,
+    subproblem_size: (usize, usize),
+    algorithm: String,
+}
+
+#[pymethods]
+impl PyAlignment {
+    fn __repr__(&self) -> String {
+        format!(
+            "Alignment(path1[{}..={}], path2[{}..={}], score={}, algorithm={})",
+            self.path1_start_index,
+            self.path1_end_index,
+            self.path2_start_index,
+            self.path2_end_index,
+            self.score,
+            self.algorithm
+        )
+    }
+}
+
+impl From<align::Alignment> for PyAlignment {
+    fn from(alignment: align::Alignment) -> Self {
+        Self {
+            alignment_path1: alignment.alignment_path1,
+            alignment_path2: alignment.alignment_path2,
+            path1_start_index: alignment.path1_start_index,
+            path1_end_index: alignment.path1_end_index,
+            path2_start_index: alignment.path2_start_index,
+            path2_end_index: alignment.path2_end_index,
+            score: alignment.score,
+            subproblem_size: alignment.subproblem_size,
+            algorithm: algorithm_name(alignment.algorithm),
+        }
+    }
+}
+
+/// An inversion found by aligning a query path to a reference path, with 1-based positions
+#[pyclass(name = "InversionCall", frozen, get_all)]
+struct PyInversionCall {
+    chromosome: String,
+    ref_path: String,
+    query_path: String,
+    ref_start: i64,
+    ref_end: i64,
+    query_start: i64,
+    query_end: i64,
+    score: i64,
+    ref_segments: Vec<i64>,
+    query_segments: Vec<i64>,
+    subproblem_size: (usize, usize),
+    algorithm: String,
+}
+
+#[pymethods]
+impl PyInversionCall {
+    fn __repr__(&self) -> String {
+        format!(
+            "InversionCall({}:{}-{} in {}:{}-{}, score={})",
+            self.ref_path,
+            self.ref_start,
+            self.ref_end,
+            self.query_path,
+            self.query_start,
+            self.query_end,
+            self.score
+        )
+    }
+}
+
+impl PyInversionCall {
+    fn new(chromosome: &str, call: alignment_interface::InversionCall) -> Self {
+        Self {
+            chromosome: chromosome.to_string(),
+            ref_path: call.ref_path_key,
+            query_path: call.query_path_key,
+            ref_start: call.ref_interval.0,
+            ref_end: call.ref_interval.1,
+            query_start: call.query_interval.0,
+            query_end: call.query_interval.1,
+            score: call.score,
+            ref_segments: call.ref_segments,
+            query_segments: call.query_segments,
+            subproblem_size: call.subproblem_size,
+            algorithm: algorithm_name(call.algorithm),
+        }
+    }
+}
+
+/// Read a GFA, which may be uncompressed, gzipped, or BGZF-compressed, optionally using a binary
+/// cache of it that is written if it doesn't exist yet or was made from a different GFA.
+///
+/// Paths can be left out to save memory by giving lists of paths, haplotypes, samples, sample
+/// prefixes ending in `*`, or regexes starting with `re:` to include or exclude. With
+/// `full_checksum`, the whole GFA is checksummed to check the cache rather than only its size,
+/// modification time, and first and last blocks.
+#[pyfunction]
+#[pyo3(signature = (
+    path, cache = None, include = Vec::new(), exclude = Vec::new(), full_checksum = false
+))]
+fn read_gfa(
+    py: Python<'_>,
+    path: PathBuf,
+    cache: Option<PathBuf>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    full_checksum: bool,
+) -> PyResult<PyPangenomeGraph> {
+    let filter = PathFilter::parse(&include, &exclude)?;
+    let checksum_mode = if full_checksum {
+        cache::ChecksumMode::Full
+    } else {
+        cache::ChecksumMode::Quick
+    };
+    let graph = py.detach(|| match cache {
+        Some(cache_path) => cache::read_gfa_cached(path, &cache_path, &filter, checksum_mode),
+        None => gfa::read_gfa_filtered(path, &filter),
+    })?;
+    Ok(PyPangenomeGraph { graph })
+}
+
+/// Align path1 to the reverse complement of path2 to find the inversions between them.
+///
+/// If `min_alignment_score` is given, every non-overlapping local alignment scoring at least that
+/// much is reported rather than only the best one in each subproblem. `scoring_scheme` is `length`,
+/// `capped:<max_length>`, or `sublinear:<exponent>`, and `gap_open` and `gap_extend_factor` are
+/// affine gap penalties for runs of unaligned segments, as for the command-line options. With
+/// `exact_lowmem`, paths longer than `max_highmem_path_length` are aligned exactly in linear
+/// memory rather than with the lowmem heuristic, however long they are. `strategy` is `dp` or
+/// `chain[:<gap_cost>]`, and with `chain`, `refine_window` steps around each end of a chain are
+/// aligned with dynamic programming if it's given.
+#[pyfunction]
+#[pyo3(signature = (
+    graph,
+    path1,
+    path2,
+    max_highmem_path_length = 10000,
+    max_lowmem_drop = 1000,
+    exact_lowmem = false,
+    max_path_length = 100000,
+    min_alignment_score = None,
+    scoring_scheme = "length",
+    gap_open = 0,
+    gap_extend_factor = 1.0,
+    strategy = "dp",
+    refine_window = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn align_paths(
+    py: Python<'_>,
+    graph: PyRef<'_, PyPangenomeGraph>,
+    path1: Vec<i64>,
+    path2: Vec<i64>,
+    max_highmem_path_length: usize,
+    max_lowmem_drop: usize,
+    exact_lowmem: bool,
+    max_path_length: usize,
+    min_alignment_score: Option<i64>,
+    scoring_scheme: &str,
+    gap_open: i64,
+    gap_extend_factor: f64,
+    strategy: &str,
+    refine_window: Option<usize>,
+) -> PyResult<Vec<PyAlignment>> {
+    let graph = &graph.graph;
+    let scoring_scheme = scoring::parse_scoring_scheme(scoring_scheme)?;
+    let gap_penalties = GapPenalties::new(gap_open, gap_extend_factor)?;
+    let strategy = align::parse_strategy(strategy)?;
+    let alignment_options = AlignmentOptions {
+        max_highmem_path_length,
+        max_lowmem_drop,
+        exact_lowmem,
+        max_path_length,
+        min_alignment_score,
+        scoring_scheme: scoring_scheme.as_ref(),
+        gap_penalties,
+        strategy,
+        refine_window,
+    };
+    let alignments = py.detach(|| {
+        align::align_paths(
+            &CompressedSteps::from(path1),
+            &CompressedSteps::from(path2),
+            graph,
+            alignment_options,
+        )
+    })?;
+    Ok(alignments.into_iter().map(PyAlignment::from).collect())
+}
+
+/// Find the inversions in every path of the graph relative to the reference, which can be the name
+/// of a path, a haplotype like `sample#1`, or a sample with only one haplotype.
+///
+/// Paths matching any of the patterns in `exclude`, which are in the same format as for
+/// `read_gfa`, aren't aligned.
+#[pyfunction]
+#[pyo3(signature = (
+    graph,
+    ref_path,
+    exclude = Vec::new(),
+    max_highmem_path_length = 10000,
+    max_lowmem_drop = 1000,
+    exact_lowmem = false,
+    max_path_length = 100000,
+    min_alignment_score = None,
+    scoring_scheme = "length",
+    gap_open = 0,
+    gap_extend_factor = 1.0,
+    strategy = "dp",
+    refine_window = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn align_all_queries(
+    py: Python<'_>,
+    graph: PyRef<'_, PyPangenomeGraph>,
+    ref_path: &str,
+    exclude: Vec<String>,
+    max_highmem_path_length: usize,
+    max_lowmem_drop: usize,
+    exact_lowmem: bool,
+    max_path_length: usize,
+    min_alignment_score: Option<i64>,
+    scoring_scheme: &str,
+    gap_open: i64,
+    gap_extend_factor: f64,
+    strategy: &str,
+    refine_window: Option<usize>,
+) -> PyResult<Vec<PyInversionCall>> {
+    let graph = &graph.graph;
+    let scoring_scheme = scoring::parse_scoring_scheme(scoring_scheme)?;
+    let gap_penalties = GapPenalties::new(gap_open, gap_extend_factor)?;
+    let strategy = align::parse_strategy(strategy)?;
+    let alignment_options = AlignmentOptions {
+        max_highmem_path_length,
+        max_lowmem_drop,
+        exact_lowmem,
+        max_path_length,
+        min_alignment_score,
+        scoring_scheme: scoring_scheme.as_ref(),
+        gap_penalties,
+        strategy,
+        refine_window,
+    };
+    let calls = py.detach(|| -> Result<Vec<PyInversionCall>, InversionError> {
+        let filter = PathFilter::parse(&[], &exclude)?;
+        let mut calls = Vec::new();
+        for chromosome_group in
+            alignment_interface::group_paths_by_chromosome(graph, ref_path, &filter)?
+        {
+            let (inversions, _) = alignment_interface::align_all_queries(
+                graph,
+                &chromosome_group,
+                alignment_options,
+            )?;
+            calls.extend(
+                inversions
+                    .into_iter()
+                    .map(|call| PyInversionCall::new(&chromosome_group.chromosome, call)),
+            );
+        }
+        Ok(calls)
+    })?;
+    Ok(calls)
+}
+
+#[pymodule]
+fn inversion_finder(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyPangenomeGraph>()?;
+    m.add_class::<PyAlignment>()?;
+    m.add_class::<PyInversionCall>()?;
+    m.add_function(wrap_pyfunction!(read_gfa, m)?)?;
+    m.add_function(wrap_pyfunction!(align_paths, m)?)?;
+    m.add_function(wrap_pyfunction!(align_all_queries, m)?)?;
+    Ok(())
+}
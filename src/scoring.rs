@@ -0,0 +1,275 @@
+use std::fmt;
+
+use crate::InversionError;
+use crate::graph::PangenomeGraph;
+
+/// A way of scoring the segments in an alignment of two paths.
+///
+/// A segment that is in both paths at the same place in the alignment adds `match_score` of its
+/// length to the score, and a segment that isn't aligned to the same segment in the other path
+/// adds `gap_score` of its length, which should be negative. Where two different segments are
+/// aligned to each other, neither of them is matched, so both of their gap scores are added.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::scoring::{self, ScoringScheme};
+///
+/// let scoring_scheme = scoring::parse_scoring_scheme("capped:1000").unwrap();
+/// assert_eq!(scoring_scheme.match_score(5000), 1000);
+/// assert_eq!(scoring_scheme.gap_score(50), -50);
+/// assert_eq!(scoring_scheme.to_string(), "capped:1000");
+///
+/// let scoring_scheme = scoring::parse_scoring_scheme("sublinear:0.5").unwrap();
+/// assert_eq!(scoring_scheme.match_score(10000), 10000);
+/// assert_eq!(scoring_scheme.gap_score(10000), -100);
+/// ```
+pub trait ScoringScheme: fmt::Debug + fmt::Display + Send + Sync {
+    /// Score of a segment of `length` bp that is aligned to the same segment in the other path
+    fn match_score(&self, length: i64) -> i64;
+
+    /// Score of a segment of `length` bp that isn't aligned to the same segment in the other path
+    fn gap_score(&self, length: i64) -> i64;
+}
+
+/// Score segments by their lengths, so that an alignment's score is the number of bases that
+/// align minus the number that don't. This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SegmentLength;
+
+impl ScoringScheme for SegmentLength {
+    fn match_score(&self, length: i64) -> i64 {
+        length
+    }
+
+    fn gap_score(&self, length: i64) -> i64 {
+        -length
+    }
+}
+
+impl fmt::Display for SegmentLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "length")
+    }
+}
+
+/// Score segments by their lengths, but count segments longer than `max_length` as if they were
+/// only `max_length` bp long, so that a few very long segments (e.g., the large nodes made by
+/// minigraph-cactus) can't outweigh everything else.
+#[derive(Debug, Clone, Copy)]
+pub struct Capped {
+    pub max_length: i64,
+}
+
+impl ScoringScheme for Capped {
+    fn match_score(&self, length: i64) -> i64 {
+        length.min(self.max_length)
+    }
+
+    fn gap_score(&self, length: i64) -> i64 {
+        -length.min(self.max_length)
+    }
+}
+
+impl fmt::Display for Capped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "capped:{}", self.max_length)
+    }
+}
+
+/// Score matched segments by their lengths, but penalize unaligned segments by their lengths to
+/// the power of `exponent`, which is between 0 and 1, so that long unaligned segments (e.g.,
+/// insertions in one path) don't break up an alignment as much as they would otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct Sublinear {
+    pub exponent: f64,
+}
+
+impl ScoringScheme for Sublinear {
+    fn match_score(&self, length: i64) -> i64 {
+        length
+    }
+
+    fn gap_score(&self, length: i64) -> i64 {
+        -((length as f64).powf(self.exponent).round() as i64)
+    }
+}
+
+impl fmt::Display for Sublinear {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sublinear:{}", self.exponent)
+    }
+}
+
+/// Parse the name of one of the built-in scoring schemes: `length` for `SegmentLength`,
+/// `capped:<max_length>` for `Capped`, or `sublinear:<exponent>` for `Sublinear`.
+pub fn parse_scoring_scheme(name: &str) -> Result<Box<dyn ScoringScheme>, InversionError> {
+    let invalid =
+        |reason: &str| InversionError::InvalidScoringScheme(format!("{} ({})", name, reason));
+    let (scheme, parameter) = match name.split_once(':') {
+        Some((scheme, parameter)) => (scheme, Some(parameter)),
+        None => (name, None),
+    };
+    match (scheme, parameter) {
+        ("length", None) => Ok(Box::new(SegmentLength)),
+        ("capped", Some(max_length)) => match max_length.parse::<i64>() {
+            Ok(max_length) if max_length > 0 => Ok(Box::new(Capped { max_length })),
+            _ => Err(invalid("the maximum length must be a positive integer")),
+        },
+        ("sublinear", Some(exponent)) => match exponent.parse::<f64>() {
+            Ok(exponent) if exponent > 0.0 && exponent <= 1.0 => {
+                Ok(Box::new(Sublinear { exponent }))
+            }
+            _ => Err(invalid("the exponent must be a number between 0 and 1")),
+        },
+        _ => Err(invalid(
+            "expected length, capped:<max_length>, or sublinear:<exponent>",
+        )),
+    }
+}
+
+/// Affine gap penalties, for scoring runs of consecutive segments of one path that aren't aligned
+/// to anything in the other path, e.g., an insertion in one of the paths.
+///
+/// A run of unaligned segments scores `-open` plus `extend_factor` times the sum of the gap scores
+/// of its segments, where `extend_factor` is between 0 and 1. With `open` greater than 0 and
+/// `extend_factor` less than 1, a long insertion costs less than the same number of segments
+/// scattered through an alignment, so it doesn't split an inversion in two. Two different segments
+/// aligned to each other aren't a gap, so they're still scored by the sum of their gap scores. The
+/// default of `open = 0` and `extend_factor = 1` scores every unaligned segment on its own.
+///
+/// # Examples
+///
+/// ```
+/// use inversion_finder::scoring::GapPenalties;
+///
+/// let gap_penalties = GapPenalties::new(1000, 0.1).unwrap();
+/// assert_eq!(gap_penalties.extension_score(-5000), -500);
+/// assert!(GapPenalties::new(-1, 0.5).is_err());
+/// assert!(GapPenalties::new(1000, 1.5).is_err());
+/// assert_eq!(GapPenalties::default().extension_score(-5000), -5000);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GapPenalties {
+    pub open: i64,
+    pub extend_factor: f64,
+}
+
+impl GapPenalties {
+    pub fn new(open: i64, extend_factor: f64) -> Result<Self, InversionError> {
+        if open < 0 {
+            return Err(InversionError::InvalidGapPenalties(format!(
+                "the gap open penalty must not be negative, but it is {}",
+                open
+            )));
+        }
+        if !(0.0..=1.0).contains(&extend_factor) {
+            return Err(InversionError::InvalidGapPenalties(format!(
+                "the gap extend factor must be a number between 0 and 1, but it is {}",
+                extend_factor
+            )));
+        }
+        Ok(Self {
+            open,
+            extend_factor,
+        })
+    }
+
+    /// Score of a segment with the given gap score when it's part of a run of unaligned segments
+    pub fn extension_score(&self, gap_score: i64) -> i64 {
+        (gap_score as f64 * self.extend_factor).round() as i64
+    }
+}
+
+impl Default for GapPenalties {
+    fn default() -> Self {
+        Self {
+            open: 0,
+            extend_factor: 1.0,
+        }
+    }
+}
+
+/// Scores of every segment of a path, worked out once per alignment subproblem rather than for
+/// every cell of the dynamic programming matrices
+pub(crate) struct PathScores {
+    /// score of each segment if it's matched
+    pub matches: Vec<i64>,
+
+    /// score of each segment if it's aligned to a different segment
+    pub gaps: Vec<i64>,
+
+    /// score of each segment if it's in a run of unaligned segments, not counting the gap open
+    /// penalty
+    pub gap_extensions: Vec<i64>,
+}
+
+impl PathScores {
+    pub fn new(
+        path: &[i64],
+        graph: &PangenomeGraph,
+        scoring_scheme: &dyn ScoringScheme,
+        gap_penalties: GapPenalties,
+    ) -> Result<Self, InversionError> {
+        let mut matches = Vec::with_capacity(path.len());
+        let mut gaps = Vec::with_capacity(path.len());
+        let mut gap_extensions = Vec::with_capacity(path.len());
+        for segment in path {
+            let length = graph.scoring_segment_length(*segment)?;
+            let gap_score = scoring_scheme.gap_score(length);
+            matches.push(scoring_scheme.match_score(length));
+            gaps.push(gap_score);
+            gap_extensions.push(gap_penalties.extension_score(gap_score));
+        }
+        Ok(Self {
+            matches,
+            gaps,
+            gap_extensions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scoring_scheme() {
+        for name in ["length", "capped:500", "sublinear:0.5"] {
+            assert_eq!(parse_scoring_scheme(name).unwrap().to_string(), name);
+        }
+        for name in [
+            "",
+            "lengths",
+            "length:5",
+            "capped",
+            "capped:0",
+            "capped:x",
+            "sublinear:2",
+            "sublinear:-0.5",
+        ] {
+            assert!(parse_scoring_scheme(name).is_err(), "{}", name);
+        }
+
+        let scoring_scheme = parse_scoring_scheme("sublinear:0.5").unwrap();
+        assert_eq!(scoring_scheme.gap_score(2), -1);
+        assert_eq!(scoring_scheme.gap_score(0), 0);
+    }
+
+    #[test]
+    fn test_gap_penalties() {
+        for extend_factor in [0.0, 0.5, 1.0] {
+            assert!(GapPenalties::new(0, extend_factor).is_ok());
+        }
+        for extend_factor in [-0.1, 1.5, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                GapPenalties::new(0, extend_factor),
+                Err(InversionError::InvalidGapPenalties(_))
+            ));
+        }
+        assert_eq!(
+            GapPenalties::new(-1, 1.0).unwrap_err().to_string(),
+            "Invalid gap penalties: the gap open penalty must not be negative, but it is -1"
+        );
+    }
+}
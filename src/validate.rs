@@ -0,0 +1,424 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use crate::InversionError;
+use crate::gfa::{self, SegmentNames};
+use crate::pansn::PanSnName;
+
+/// How serious a problem found in a GFA is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// the GFA can't be used to find inversions, or the results would be wrong
+    Error,
+
+    /// the GFA can be used, but probably not in the way that was intended
+    Warning,
+}
+
+/// A problem found in a GFA
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+
+    /// 1-based number of the line the problem is on, if it is on a specific line
+    pub line: Option<usize>,
+
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.line {
+            Some(line) => write!(f, "line {}: {}: {}", line, severity, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// Everything we need to remember about a GFA while reading through it to validate it
+#[derive(Default)]
+struct Validator {
+    issues: Vec<ValidationIssue>,
+    segment_names: SegmentNames,
+
+    /// map of segment ID to the line of its S-line
+    segment_lines: HashMap<i64, usize>,
+
+    /// map of segment ID to the first line it is used on in a path or link
+    segment_uses: HashMap<i64, usize>,
+
+    /// canonical forms of all links, as returned by `canonical_adjacency`
+    links: HashSet<(i64, i64)>,
+
+    /// map of canonical form of each adjacency in a path to the index in `path_lines` of the first
+    /// path it is used in, and the adjacency as it appears in that path
+    adjacencies: HashMap<(i64, i64), (usize, (i64, i64))>,
+
+    /// names of paths and the lines they are on, in the order they were read
+    path_lines: Vec<(String, usize)>,
+
+    /// map of path name to index in `path_lines`
+    path_indices: HashMap<String, usize>,
+}
+
+/// Put an adjacency between two oriented segments in canonical form.
+///
+/// Going from `a` to `b` is the same as going from `-b` to `-a`, just on the other strand, so both
+/// are represented by whichever of the two comes first.
+fn canonical_adjacency(a: i64, b: i64) -> (i64, i64) {
+    (a, b).min((-b, -a))
+}
+
+impl Validator {
+    fn error(&mut self, line: usize, message: String) {
+        self.issues.push(ValidationIssue {
+            severity: Severity::Error,
+            line: Some(line),
+            message,
+        });
+    }
+
+    /// Check that a line has at least a certain number of fields, reporting an error if not.
+    fn check_field_count(&mut self, fields: &[&str], min_fields: usize, line: usize) -> bool {
+        if fields.len() < min_fields {
+            self.error(
+                line,
+                format!(
+                    "{}-line has {} fields but should have at least {}",
+                    fields[0],
+                    fields.len(),
+                    min_fields
+                ),
+            );
+            return false;
+        }
+        true
+    }
+
+    fn use_segment(&mut self, segment: i64, line: usize) {
+        self.segment_uses.entry(segment.abs()).or_insert(line);
+    }
+
+    fn add_segment(&mut self, fields: &[&str], line: usize) -> Result<(), InversionError> {
+        if !self.check_field_count(fields, 3, line) {
+            return Ok(());
+        }
+        let segment = self.segment_names.intern(fields[1])?;
+        if let Some(previous_line) = self.segment_lines.get(&segment) {
+            let message = format!(
+                "Segment '{}' already has an S-line on line {}",
+                fields[1], previous_line
+            );
+            self.error(line, message);
+        } else {
+            self.segment_lines.insert(segment, line);
+        }
+        if let Err(err) = gfa::parse_segment_length(fields) {
+            self.error(line, err.to_string());
+        }
+        Ok(())
+    }
+
+    fn add_link(&mut self, fields: &[&str], line: usize) -> Result<(), InversionError> {
+        if !self.check_field_count(fields, 5, line) {
+            return Ok(());
+        }
+        let mut oriented_segments = [0; 2];
+        for (k, (name, orientation)) in [(fields[1], fields[2]), (fields[3], fields[4])]
+            .into_iter()
+            .enumerate()
+        {
+            let segment = self.segment_names.intern(name)?;
+            self.use_segment(segment, line);
+            oriented_segments[k] = match orientation {
+                "+" => segment,
+                "-" => -segment,
+                _ => {
+                    self.error(line, format!("Invalid link orientation '{}'", orientation));
+                    return Ok(());
+                }
+            };
+        }
+        self.links.insert(canonical_adjacency(
+            oriented_segments[0],
+            oriented_segments[1],
+        ));
+        Ok(())
+    }
+
+    fn add_path(&mut self, path_name: &str, path: &[i64], line: usize) {
+        if let Some(previous_index) = self.path_indices.get(path_name) {
+            let message = format!(
+                "Path '{}' has the same name as the path on line {}",
+                path_name, self.path_lines[*previous_index].1
+            );
+            self.error(line, message);
+            return;
+        }
+        let path_index = self.path_lines.len();
+        self.path_lines.push((path_name.to_string(), line));
+        self.path_indices.insert(path_name.to_string(), path_index);
+
+        for segment in path {
+            self.use_segment(*segment, line);
+        }
+        for step in path.windows(2) {
+            self.adjacencies
+                .entry(canonical_adjacency(step[0], step[1]))
+                .or_insert((path_index, (step[0], step[1])));
+        }
+    }
+
+    fn add_p_line(&mut self, fields: &[&str], line: usize) -> Result<(), InversionError> {
+        if !self.check_field_count(fields, 3, line) {
+            return Ok(());
+        }
+        match gfa::parse_gfa_path(fields[2], &mut self.segment_names) {
+            Ok(path) => {
+                if let Err(err) = gfa::parse_gfa_overlaps(fields.get(3).unwrap_or(&"*"), path.len())
+                {
+                    self.error(line, err.to_string());
+                }
+                self.add_path(fields[1], &path, line);
+            }
+            Err(InversionError::Overflow(e)) => return Err(InversionError::Overflow(e)),
+            Err(err) => self.error(line, err.to_string()),
+        }
+        Ok(())
+    }
+
+    fn add_w_line(&mut self, fields: &[&str], line: usize) -> Result<(), InversionError> {
+        if !self.check_field_count(fields, 7, line) {
+            return Ok(());
+        }
+        for (field, position) in [("start", fields[4]), ("end", fields[5])] {
+            if position != "*" && position.parse::<i64>().is_err() {
+                self.error(
+                    line,
+                    format!("Invalid walk {} position '{}'", field, position),
+                );
+            }
+        }
+        match gfa::parse_gfa_walk(fields[6], &mut self.segment_names) {
+            Ok(walk) => {
+                let walk_name = gfa::make_walk_name(fields[1], fields[2], fields[3]);
+                self.add_path(&walk_name, &walk, line);
+            }
+            Err(InversionError::Overflow(e)) => return Err(InversionError::Overflow(e)),
+            Err(err) => self.error(line, err.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Check that paths are named in PanSN format, i.e., `sample#haplotype#contig`.
+    fn check_path_names(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let mut warning = |line: usize, message: String| {
+            issues.push(ValidationIssue {
+                severity: Severity::Warning,
+                line: Some(line),
+                message,
+            })
+        };
+        let mut non_pansn_paths = Vec::new();
+        for (path_name, line) in &self.path_lines {
+            let pansn_name = PanSnName::parse(path_name);
+            let Some(haplotype) = pansn_name.haplotype else {
+                non_pansn_paths.push((path_name, *line));
+                continue;
+            };
+            if pansn_name.sample.is_empty() || haplotype.is_empty() || pansn_name.contig.is_empty()
+            {
+                warning(
+                    *line,
+                    format!("Path '{}' has an empty PanSN field", path_name),
+                );
+            }
+            if haplotype.parse::<u64>().is_err() {
+                warning(
+                    *line,
+                    format!(
+                        "Path '{}' has PanSN haplotype '{}', which is not a non-negative integer",
+                        path_name, haplotype
+                    ),
+                );
+            }
+        }
+        // a graph with no PanSN names at all is fine, but a mix probably means a mistake
+        if non_pansn_paths.len() < self.path_lines.len() {
+            for (path_name, line) in non_pansn_paths {
+                warning(
+                    line,
+                    format!(
+                        "Path '{}' is not named in PanSN format (sample#haplotype#contig) like the \
+                         other paths",
+                        path_name
+                    ),
+                );
+            }
+        }
+        issues
+    }
+
+    /// Check everything that can only be checked once the whole GFA has been read.
+    fn finish(mut self) -> Vec<ValidationIssue> {
+        // segments used in a path or link but never defined
+        let mut missing_segments: Vec<(usize, i64)> = self
+            .segment_uses
+            .iter()
+            .filter(|(segment, _)| !self.segment_lines.contains_key(segment))
+            .map(|(segment, line)| (*line, *segment))
+            .collect();
+        missing_segments.sort();
+        for (line, segment) in missing_segments {
+            let message = format!(
+                "Segment '{}' is used here but has no S-line",
+                self.segment_names.name(segment).unwrap()
+            );
+            self.error(line, message);
+        }
+
+        // adjacencies in paths without a matching L-line
+        if self.links.is_empty() {
+            if !self.adjacencies.is_empty() {
+                self.issues.push(ValidationIssue {
+                    severity: Severity::Warning,
+                    line: None,
+                    message: "GFA has no L-lines, so path adjacencies can't be checked".to_string(),
+                });
+            }
+        } else {
+            // only the first path using each adjacency is kept track of, so that the paths don't
+            // have to be kept in memory, so the adjacencies are counted for the whole graph and
+            // only the first one is given as an example
+            let missing_links: Vec<(usize, (i64, i64))> = self
+                .adjacencies
+                .iter()
+                .filter(|(canonical, _)| !self.links.contains(canonical))
+                .map(|(_, first_use)| *first_use)
+                .collect();
+            if let Some((path_index, adjacency)) = missing_links.iter().min() {
+                let (path_name, line) = &self.path_lines[*path_index];
+                let message = format!(
+                    "Paths in the GFA use {} adjacencies with no matching L-line, e.g., {} -> {} \
+                     in path '{}'",
+                    missing_links.len(),
+                    self.segment_names.format_oriented(adjacency.0),
+                    self.segment_names.format_oriented(adjacency.1),
+                    path_name,
+                );
+                self.error(*line, message);
+            }
+        }
+
+        let path_name_issues = self.check_path_names();
+        self.issues.extend(path_name_issues);
+
+        self.issues
+            .sort_by_key(|issue| issue.line.unwrap_or(usize::MAX));
+        self.issues
+    }
+}
+
+/// Check a GFA for problems that would stop it from being used to find inversions.
+///
+/// Unlike `gfa::read_gfa`, this doesn't stop at the first problem, and it doesn't keep the paths
+/// in memory, so it can be used to find all of the problems in a GFA at once. It checks for:
+///
+/// * malformed lines, e.g., with too few fields or invalid paths
+/// * segments used in paths or links that have no S-line, or that have more than one
+/// * paths with the same name as another path
+/// * adjacencies in paths that have no matching L-line
+/// * path names that aren't in PanSN format
+///
+/// # Arguments
+///
+/// * `path`: path to the GFA to validate, which may be uncompressed, gzipped, or BGZF-compressed
+///
+/// # Returns
+///
+/// * all of the problems found, ordered by line number
+pub fn validate_gfa(path: PathBuf) -> Result<Vec<ValidationIssue>, InversionError> {
+    let reader = gfa::open_gfa(&path)?;
+    let mut validator = Validator::default();
+
+    for (i, line_result) in reader.lines().enumerate() {
+        let line = line_result
+            .map_err(|err| InversionError::GfaParse(format!("Reading error: {}", err)))?;
+        let fields: Vec<&str> = line.split("\t").collect();
+        match fields[0] {
+            "S" => validator.add_segment(&fields, i + 1)?,
+            "L" => validator.add_link(&fields, i + 1)?,
+            "P" => validator.add_p_line(&fields, i + 1)?,
+            "W" => validator.add_w_line(&fields, i + 1)?,
+            _ => {}
+        }
+    }
+
+    Ok(validator.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn test_validate_gfa() {
+        let path = std::env::temp_dir().join(format!("{}_test_validate.gfa", std::process::id()));
+        File::create(&path)
+            .unwrap()
+            .write_all(
+                concat!(
+                    "H\tVN:Z:1.1\n",
+                    "S\t1\tACGT\n",
+                    "S\t2\t*\n",
+                    "S\t1\tACGT\n",
+                    "S\t3\tACGT\n",
+                    "L\t1\t+\t2\t+\t0M\n",
+                    "L\t3\t-\t2\t-\t0M\n",
+                    "P\tsampleA#0#chr1\t1+,2+,3+\t*\n",
+                    "P\tsampleA#0#chr1\t1+,2+\t*\n",
+                    "P\tsampleB#0#chr1\t1+,3+,4+\t*\n",
+                    "P\tsampleC\n",
+                    "W\tsampleD\tone\tchr1\t0\t*\t>1>2\n",
+                    "P\tsampleE\t1+\t*\n",
+                    "P\tsampleF#0#chr1\t1+,2+,3+,1+\t*\n",
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        let issues = validate_gfa(path.clone()).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let summary: Vec<(Severity, Option<usize>)> = issues
+            .iter()
+            .map(|issue| (issue.severity, issue.line))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Severity::Error, Some(3)),    // no sequence or LN tag
+                (Severity::Error, Some(4)),    // duplicate segment
+                (Severity::Error, Some(9)),    // duplicate path
+                (Severity::Error, Some(10)),   // missing segment 4
+                (Severity::Error, Some(10)),   // missing link 1+ -> 3+
+                (Severity::Error, Some(11)),   // too few fields
+                (Severity::Warning, Some(12)), // non-integer haplotype
+                (Severity::Warning, Some(13)), // not PanSN
+            ]
+        );
+        assert_eq!(
+            issues[4].message,
+            "Paths in the GFA use 3 adjacencies with no matching L-line, e.g., 1+ -> 3+ in path \
+             'sampleB#0#chr1'"
+        );
+    }
+}
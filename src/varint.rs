@@ -0,0 +1,80 @@
+//! LEB128 varints, with signed integers zigzag-encoded first so that small negative numbers take up
+//! as little space as small positive ones. These are used for both the compressed steps of paths
+//! and the graph cache.
+
+use std::io::{self, Read};
+
+/// Maximum number of bytes in a varint, which is enough for any `u128`
+pub(crate) const MAX_VARINT_LENGTH: usize = 19;
+
+/// Zigzag-encode a signed integer, mapping 0, -1, 1, -2, ... to 0, 1, 2, 3, ...
+pub(crate) fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Undo `zigzag`.
+pub(crate) fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+/// Encode a value as a varint in `buffer`, returning the bytes of the varint.
+pub(crate) fn encode_varint(mut value: u128, buffer: &mut [u8; MAX_VARINT_LENGTH]) -> &[u8] {
+    let mut length = 0;
+    while value >= 0x80 {
+        buffer[length] = value as u8 | 0x80;
+        value >>= 7;
+        length += 1;
+    }
+    buffer[length] = value as u8;
+    &buffer[..=length]
+}
+
+/// Read a varint, failing with `io::ErrorKind::UnexpectedEof` if the input ends in the middle of
+/// it, or with `io::ErrorKind::InvalidData` if it's longer than `MAX_VARINT_LENGTH` bytes.
+pub(crate) fn read_varint(input: &mut impl Read) -> io::Result<u128> {
+    let mut value = 0u128;
+    for shift in (0..7 * MAX_VARINT_LENGTH).step_by(7) {
+        let mut byte = [0];
+        input.read_exact(&mut byte)?;
+        value |= u128::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "integer is too long",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varints() {
+        let values = [0, 1, 127, 128, 1 << 40, u128::from(u64::MAX), u128::MAX];
+        let mut bytes = Vec::new();
+        let mut buffer = [0; MAX_VARINT_LENGTH];
+        for value in values {
+            bytes.extend_from_slice(encode_varint(value, &mut buffer));
+        }
+        assert_eq!(bytes[..4], [0, 1, 127, 0x80]);
+        let mut input = bytes.as_slice();
+        for value in values {
+            assert_eq!(read_varint(&mut input).unwrap(), value);
+        }
+        assert!(input.is_empty());
+
+        // truncated and overlong varints
+        let error = read_varint(&mut [0x80].as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let error = read_varint(&mut [0xff; MAX_VARINT_LENGTH + 1].as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        for value in [0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+        assert_eq!(zigzag(-1), 1);
+    }
+}